@@ -0,0 +1,33 @@
+use advent_of_code_2020::day7::query::{evaluate, parse_query};
+use advent_of_code_2020::day7::validate::validate;
+use advent_of_code_2020::day7::{constraints, generator};
+use std::io::BufRead;
+
+/// usage: day7_query [rules file], then one query per line on stdin
+fn main() {
+    let path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "input/2020/day7.txt".to_string());
+    let input = std::fs::read_to_string(&path).expect("could not read rules file");
+    let errors = validate(&constraints(&input));
+    if !errors.is_empty() {
+        for error in errors {
+            eprintln!("{}", error);
+        }
+        std::process::exit(1);
+    }
+    let rules = generator(&input);
+    for line in std::io::stdin().lock().lines() {
+        let line = line.unwrap();
+        if line.trim().is_empty() {
+            continue;
+        }
+        match parse_query(&line) {
+            Ok(query) => match evaluate(&rules, &query) {
+                Ok(answer) => println!("{}", answer),
+                Err(e) => eprintln!("{}", e),
+            },
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+}
@@ -0,0 +1,19 @@
+use advent_of_code_2020::day8::debugger::repl;
+use advent_of_code_2020::day8::parse_program;
+
+/// usage: day8_debug [program file], then debugger commands on stdin (try `help`)
+fn main() {
+    let path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "input/2020/day8.txt".to_string());
+    let input = std::fs::read_to_string(&path).expect("could not read program file");
+    let program = match parse_program(&input) {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let stdin = std::io::stdin();
+    repl(program, stdin.lock(), std::io::stdout()).unwrap();
+}
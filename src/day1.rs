@@ -1,5 +1,7 @@
-use itertools::Itertools;
-use std::collections::HashSet;
+use crate::day9::two_sum;
+use crate::overflow;
+use num_traits::PrimInt;
+use std::hash::Hash;
 
 /// https://adventofcode.com/2020/day/1
 
@@ -8,42 +10,32 @@ pub fn generator(input: &str) -> Vec<u32> {
     input.split("\n").flat_map(|x| x.parse()).collect()
 }
 
+/// three entries at different positions adding up to `target`
+pub fn find_triple<T: PrimInt + Hash>(input: &[T], target: T) -> Option<(T, T, T)> {
+    input.iter().enumerate().find_map(|(index, a)| {
+        let rest = target.checked_sub(a)?;
+        two_sum(&input[index + 1..], rest).map(|(b, c)| (*a, b, c))
+    })
+}
+
+/// entries adding up to 2020 multiply to at most 1010², or 673² * 674 for three of them, so the
+/// products can't overflow a u32 and unwrapping them is safe
 #[aoc(day1, part1)]
 pub fn solve_part1(input: &[u32]) -> u32 {
-    input
-        .iter()
-        .zip(std::iter::repeat(
-            &input.iter().cloned().collect::<HashSet<u32>>(),
-        ))
-        .find(|(x, nums)| nums.contains(&(2020 - **x)))
-        .map(|(x, _)| x * (2020 - *x))
-        .unwrap()
+    let (a, b) = two_sum(input, 2020).unwrap();
+    overflow::product(&[a, b]).unwrap()
 }
 
 #[aoc(day1, part2)]
 pub fn solve_part2(input: &[u32]) -> u32 {
-    input
-        .iter()
-        .sorted()
-        .flat_map(|a| {
-            input
-                .iter()
-                .filter(|x| **x < (2020 - *a))
-                .map(|x| (a, x))
-                .collect::<Vec<_>>()
-                .into_iter()
-        })
-        .zip(std::iter::repeat(
-            &input.iter().cloned().collect::<HashSet<u32>>(),
-        ))
-        .find(|((a, b), nums)| nums.contains(&(2020 - **a - **b)))
-        .map(|((a, b), _)| *a * *b * (2020 - *a - *b))
-        .unwrap()
+    let (a, b, c) = find_triple(input, 2020).unwrap();
+    overflow::product(&[a, b, c]).unwrap()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::overflow::Overflow;
 
     #[test]
     fn it_works_part1() {
@@ -66,4 +58,26 @@ mod tests {
 1456";
         assert_eq!(241861950, solve_part2(&generator(provided)));
     }
+
+    #[test]
+    fn it_handles_entries_bigger_than_the_target() {
+        assert_eq!(Some((1000, 1020)), two_sum(&[4000u32, 1000, 1020], 2020));
+        assert_eq!(None, two_sum(&[1010u32], 2020));
+        assert_eq!(Some((1010, 1010)), two_sum(&[1010u32, 1010], 2020));
+        assert_eq!(None, find_triple(&[u64::MAX, 1, 2019], 2020));
+    }
+
+    #[test]
+    fn it_works_for_other_integers() {
+        assert_eq!(Some((-5, 2025)), two_sum(&[-5i64, 7, 2025], 2020));
+        assert_eq!(
+            Some((u128::MAX - 1, 1)),
+            two_sum(&[u128::MAX - 1, 1], u128::MAX)
+        );
+        assert_eq!(Some((i8::MIN, 1, 2)), find_triple(&[i8::MIN, 1, 2], -125));
+        assert_eq!(
+            Err(Overflow::Mul),
+            overflow::product(&[100_000u32, 100_000])
+        );
+    }
 }
@@ -1,55 +1,326 @@
-use std::collections::HashMap;
+use crate::overflow::{self, Overflow};
 use itertools::Itertools;
-use cached::UnboundCache;
-use cached::proc_macro::cached;
+use std::collections::BTreeMap;
 
 /// https://adventofcode.com/2020/day/10
 
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct ChainConfig {
+    /// largest joltage increase an adapter accepts
+    pub max_step: usize,
+    /// how far above the highest adapter the device is rated, at least 1 so the highest adapter
+    /// can plug into it
+    pub device_offset: usize,
+    pub outlet: usize,
+}
+
+impl Default for ChainConfig {
+    fn default() -> Self {
+        ChainConfig {
+            max_step: 3,
+            device_offset: 3,
+            outlet: 0,
+        }
+    }
+}
+
+/// the outlet, every adapter and the device as nodes of a dag, numbered in joltage order so
+/// every edge goes from a lower node to a higher one
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct AdapterChain {
+    joltages: Vec<usize>,
+    max_step: usize,
+}
+
+impl AdapterChain {
+    /// adapters rated at or below the outlet can never be plugged in, so they're left out
+    pub fn new(adapters: &[usize], config: &ChainConfig) -> Self {
+        assert!(
+            config.device_offset > 0,
+            "the device has to be rated above the highest adapter"
+        );
+        let mut joltages = adapters
+            .iter()
+            .filter(|x| **x > config.outlet)
+            .cloned()
+            .sorted()
+            .collect_vec();
+        let device = joltages.last().cloned().unwrap_or(config.outlet) + config.device_offset;
+        joltages.insert(0, config.outlet);
+        joltages.push(device);
+        AdapterChain {
+            joltages,
+            max_step: config.max_step,
+        }
+    }
+
+    /// joltage of every node, starting with the outlet and ending with the device
+    pub fn joltages(&self) -> &[usize] {
+        &self.joltages
+    }
+
+    pub fn max_step(&self) -> usize {
+        self.max_step
+    }
+
+    /// node index of the outlet, unlike `ChainConfig::outlet` which is its joltage
+    pub fn outlet_node(&self) -> usize {
+        0
+    }
+
+    /// node index of the device
+    pub fn device_node(&self) -> usize {
+        self.joltages.len() - 1
+    }
+
+    /// nodes which can be plugged into `node`, an adapter never takes its own joltage
+    pub fn successors(&self, node: usize) -> Vec<usize> {
+        let joltage = self.joltages[node];
+        (node + 1..self.joltages.len())
+            .take_while(|x| self.joltages[*x] <= joltage + self.max_step)
+            .filter(|x| self.joltages[*x] > joltage)
+            .collect()
+    }
+}
+
 #[aoc_generator(day10)]
-pub fn generator(input: &str) -> HashMap<usize, Vec<usize>> {
-    let mut nums = input
+pub fn generator(input: &str) -> AdapterChain {
+    let adapters = input
         .lines()
         .filter_map(|x| x.parse::<usize>().ok())
-        .sorted()
         .collect_vec();
-    nums.push(nums.last().unwrap() + 3);
-    nums.push(0);
-    nums.push(0);
-    nums.push(0);
-    nums.insert(0, 0);
-
-    nums
-        .windows(4)
-        .map(|x| (x[0], x.into_iter().filter(|y| **y <= x[0] + 3 && **y != x[0]).cloned().collect_vec()))
+    AdapterChain::new(&adapters, &ChainConfig::default())
+}
+
+/// a jump between neighbouring joltages too big for any adapter to take
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct Gap {
+    /// node just below the gap
+    pub node: usize,
+    pub from: usize,
+    pub to: usize,
+}
+
+/// how often each difference occurs between neighbouring joltages, which is the chain using
+/// every adapter
+pub fn differences(input: &AdapterChain) -> BTreeMap<usize, usize> {
+    let mut histogram = BTreeMap::new();
+    for x in input.joltages().windows(2) {
+        *histogram.entry(x[1] - x[0]).or_insert(0) += 1;
+    }
+    histogram
+}
+
+/// every place the chain is broken, the device can't be reached at all if there are any
+pub fn invalid_gaps(input: &AdapterChain) -> Vec<Gap> {
+    input
+        .joltages()
+        .windows(2)
+        .enumerate()
+        .filter(|(_, x)| x[1] - x[0] > input.max_step())
+        .map(|(node, x)| Gap {
+            node,
+            from: x[0],
+            to: x[1],
+        })
         .collect()
 }
 
 #[aoc(day10, part1)]
-pub fn solve_part1(input: &HashMap<usize, Vec<usize>>) -> usize {
-    let start = input.keys().min().unwrap().clone();
-    let end = input.keys().max().unwrap().clone();
-    let path = pathfinding::directed::dfs::dfs(start, |x| input.get(x).unwrap().clone(), |x| *x == end).unwrap();
-    path.windows(2).filter(|x| x[1] - x[0] == 1).count() * path.windows(2).filter(|x| x[1] - x[0] == 3).count()
+pub fn solve_part1(input: &AdapterChain) -> usize {
+    let histogram = differences(input);
+    histogram.get(&1).unwrap_or(&0) * histogram.get(&3).unwrap_or(&0)
+}
+
+/// lazily walks every way from the outlet to the device, yielding the joltages along each one
+/// from the outlet to the device, in lexicographic order of the nodes used
+pub struct Arrangements<'a> {
+    chain: &'a AdapterChain,
+    /// nodes the device can be reached from, nothing else is ever pushed so every step heads
+    /// towards the next arrangement
+    finishes: Vec<bool>,
+    /// the current path, with the successors of each node which haven't been tried yet
+    stack: Vec<(usize, Vec<usize>)>,
+}
+
+impl<'a> Arrangements<'a> {
+    pub fn new(chain: &'a AdapterChain) -> Self {
+        let mut arrangements = Arrangements {
+            chain,
+            finishes: finishes(chain),
+            stack: vec![],
+        };
+        if arrangements.finishes[chain.outlet_node()] {
+            let untried = arrangements.untried(chain.outlet_node());
+            arrangements.stack.push((chain.outlet_node(), untried));
+        }
+        arrangements
+    }
+
+    /// reversed so popping gives the lowest one first
+    fn untried(&self, node: usize) -> Vec<usize> {
+        self.chain
+            .successors(node)
+            .into_iter()
+            .filter(|x| self.finishes[*x])
+            .rev()
+            .collect()
+    }
+}
+
+impl<'a> Iterator for Arrangements<'a> {
+    type Item = Vec<usize>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((_, untried)) = self.stack.last_mut() {
+            match untried.pop() {
+                Some(node) if node == self.chain.device_node() => {
+                    return Some(
+                        self.stack
+                            .iter()
+                            .map(|(x, _)| *x)
+                            .chain(std::iter::once(node))
+                            .map(|x| self.chain.joltages()[x])
+                            .collect(),
+                    );
+                }
+                Some(node) => {
+                    let untried = self.untried(node);
+                    self.stack.push((node, untried));
+                }
+                None => {
+                    self.stack.pop();
+                }
+            }
+        }
+        None
+    }
+}
+
+/// at most `limit` arrangements, for looking at small inputs without enumerating all of them
+pub fn arrangements(input: &AdapterChain, limit: usize) -> impl Iterator<Item = Vec<usize>> + '_ {
+    Arrangements::new(input).take(limit)
+}
+
+/// for every node, whether the device can be reached from it
+fn finishes(input: &AdapterChain) -> Vec<bool> {
+    let mut finishes = vec![false; input.joltages().len()];
+    for node in (0..finishes.len()).rev() {
+        finishes[node] =
+            node == input.device_node() || input.successors(node).iter().any(|x| finishes[*x]);
+    }
+    finishes
+}
+
+/// number of distinct ways to get from node `from` to node `to`
+///
+/// nodes are in joltage order so every way into a node is known by the time it's reached
+fn count_paths(input: &AdapterChain, from: usize, to: usize) -> Result<u128, Overflow> {
+    let mut ways = vec![0u128; input.joltages().len()];
+    ways[from] = 1;
+    for node in from..to {
+        for next in input.successors(node) {
+            ways[next] = overflow::add(ways[next], ways[node])?;
+        }
+    }
+    Ok(ways[to])
+}
+
+/// number of distinct ways to get from the outlet to the device, a run of about 150 consecutive
+/// joltages is already more than a u128 holds
+pub fn count_arrangements(input: &AdapterChain) -> Result<u128, Overflow> {
+    count_paths(input, input.outlet_node(), input.device_node())
+}
+
+/// a stretch between two nodes which are on every path, with optional adapters in between
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct Segment {
+    pub start: usize,
+    pub end: usize,
+    /// ways to get from `start` to `end`, which don't depend on any other segment
+    pub arrangements: u128,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Removability {
+    /// adapters on every path from the outlet to the device
+    pub mandatory: Vec<usize>,
+    /// every stretch between mandatory nodes with other adapters in it
+    pub segments: Vec<Segment>,
+}
+
+impl Removability {
+    /// segments are independent so this is the same as `count_arrangements`, but it can still
+    /// overflow when every segment fits on its own
+    pub fn arrangements(&self) -> Result<u128, Overflow> {
+        overflow::product(self.segments.iter().map(|x| &x.arrangements))
+    }
 }
 
-#[cached(
-type = "UnboundCache<usize, usize>",
-create = "{ UnboundCache::new() }",
-convert = r#"{ node << 16 | target}"#
-)]
-fn total_paths(input: &HashMap<usize, Vec<usize>>, node: usize, target: usize) -> usize {
-    if node == target {
-        1
-    } else {
-        input.get(&node).unwrap().iter().filter(|x| **x != 0).map(|x| total_paths(input, *x, target)).sum()
+/// which adapters can be dropped and how the arrangements break down between them
+///
+/// a node on some path is on every path exactly when no edge between two nodes on paths jumps
+/// over it, so one sweep in joltage order finds them, if the device can't be reached at all the
+/// whole chain is a single segment with no arrangements
+pub fn removability(input: &AdapterChain) -> Result<Removability, Overflow> {
+    let len = input.joltages().len();
+    let mut reached = vec![false; len];
+    reached[input.outlet_node()] = true;
+    for node in 0..len {
+        if reached[node] {
+            for next in input.successors(node) {
+                reached[next] = true;
+            }
+        }
+    }
+    let finishes = finishes(input);
+    let on_path = |x: usize| reached[x] && finishes[x];
+    if !on_path(input.outlet_node()) {
+        return Ok(Removability {
+            mandatory: vec![],
+            segments: vec![Segment {
+                start: input.outlet_node(),
+                end: input.device_node(),
+                arrangements: 0,
+            }],
+        });
+    }
+
+    let mut boundaries = vec![];
+    let mut furthest = 0;
+    for node in (0..len).filter(|x| on_path(*x)) {
+        if furthest <= node {
+            boundaries.push(node);
+        }
+        furthest = input
+            .successors(node)
+            .into_iter()
+            .filter(|x| on_path(*x))
+            .fold(furthest, usize::max);
     }
+    Ok(Removability {
+        mandatory: boundaries
+            .iter()
+            .cloned()
+            .filter(|x| *x != input.outlet_node() && *x != input.device_node())
+            .collect(),
+        segments: boundaries
+            .windows(2)
+            .filter(|x| x[1] - x[0] > 1)
+            .map(|x| {
+                Ok(Segment {
+                    start: x[0],
+                    end: x[1],
+                    arrangements: count_paths(input, x[0], x[1])?,
+                })
+            })
+            .collect::<Result<_, _>>()?,
+    })
 }
 
 #[aoc(day10, part2)]
-pub fn solve_part2(input: &HashMap<usize, Vec<usize>>) -> usize {
-    let start = input.keys().min().unwrap().clone();
-    let end = input.keys().max().unwrap().clone();
-    total_paths(input, start, end)
+pub fn solve_part2(input: &AdapterChain) -> u128 {
+    count_arrangements(input).unwrap()
 }
 
 #[cfg(test)]
@@ -72,4 +343,228 @@ mod tests {
         assert_eq!(35, solve_part1(&generator(provided)))
     }
 
+    #[test]
+    fn it_solves_part2() {
+        let provided = "16
+10
+15
+5
+1
+11
+7
+19
+6
+12
+4";
+        assert_eq!(8, solve_part2(&generator(provided)));
+        let provided = AdapterChain::new(
+            &[
+                28, 33, 18, 42, 31, 14, 46, 20, 48, 47, 24, 23, 49, 45, 19, 38, 39, 11, 1, 32, 25,
+                35, 8, 17, 7, 9, 4, 2, 34, 10, 3,
+            ],
+            &ChainConfig::default(),
+        );
+        assert_eq!(19208, solve_part2(&provided));
+        // nothing is cached between calls, so going back to the first chain still works
+        assert_eq!(
+            8,
+            solve_part2(&generator("16\n10\n15\n5\n1\n11\n7\n19\n6\n12\n4"))
+        );
+    }
+
+    #[test]
+    fn it_counts_long_chains() {
+        // with every joltage from 1 to n the counts are the tribonacci numbers, too big for a u64
+        let chain = AdapterChain::new(&(1..=120).collect_vec(), &ChainConfig::default());
+        let mut tribonacci = vec![1u128, 1, 2];
+        while tribonacci.len() <= 120 {
+            let n = tribonacci.len();
+            tribonacci.push(tribonacci[n - 1] + tribonacci[n - 2] + tribonacci[n - 3]);
+        }
+        assert!(tribonacci[120] > u64::MAX as u128);
+        assert_eq!(Ok(tribonacci[120]), count_arrangements(&chain));
+        let chain = AdapterChain::new(&(1..=200).collect_vec(), &ChainConfig::default());
+        assert_eq!(Err(Overflow::Add), count_arrangements(&chain));
+    }
+
+    #[test]
+    fn it_counts_differences() {
+        let provided = generator("16\n10\n15\n5\n1\n11\n7\n19\n6\n12\n4");
+        assert_eq!(
+            vec![(1, 7), (3, 5)],
+            differences(&provided).into_iter().collect_vec()
+        );
+        assert!(invalid_gaps(&provided).is_empty());
+        let provided = AdapterChain::new(&[1, 2, 2, 6, 7], &ChainConfig::default());
+        assert_eq!(
+            vec![(0, 1), (1, 3), (3, 1), (4, 1)],
+            differences(&provided).into_iter().collect_vec()
+        );
+    }
+
+    #[test]
+    fn it_finds_invalid_gaps() {
+        let provided = AdapterChain::new(&[1, 5, 6, 12], &ChainConfig::default());
+        assert_eq!(
+            vec![
+                Gap {
+                    node: 1,
+                    from: 1,
+                    to: 5
+                },
+                Gap {
+                    node: 3,
+                    from: 6,
+                    to: 12
+                }
+            ],
+            invalid_gaps(&provided)
+        );
+        assert_eq!(Ok(0), count_arrangements(&provided));
+        assert_eq!(None, Arrangements::new(&provided).next());
+        // without pruning this would try every way through the first 45 adapters first
+        let provided = AdapterChain::new(
+            &(1..=45).chain(std::iter::once(100)).collect_vec(),
+            &ChainConfig::default(),
+        );
+        assert_eq!(None, Arrangements::new(&provided).next());
+    }
+
+    #[test]
+    fn it_enumerates_arrangements() {
+        let provided = generator("16\n10\n15\n5\n1\n11\n7\n19\n6\n12\n4");
+        let all = Arrangements::new(&provided).collect_vec();
+        assert_eq!(8, all.len());
+        assert_eq!(provided.joltages(), &all[0][..]);
+        assert_eq!(vec![0, 1, 4, 7, 10, 12, 15, 16, 19, 22], all[7]);
+        assert_eq!(all.len(), all.iter().unique().count());
+        assert_eq!(all[..3].to_vec(), arrangements(&provided, 3).collect_vec());
+    }
+
+    #[test]
+    fn it_enumerates_lazily() {
+        let chain = AdapterChain::new(&(1..=120).collect_vec(), &ChainConfig::default());
+        let first = arrangements(&chain, 2).collect_vec();
+        assert_eq!(chain.joltages(), &first[0][..]);
+        assert_eq!(&chain.joltages()[..119], &first[1][..119]);
+        assert_eq!(vec![120, 123], first[1][119..].to_vec());
+    }
+
+    #[test]
+    fn it_finds_mandatory_adapters() {
+        let provided = generator("16\n10\n15\n5\n1\n11\n7\n19\n6\n12\n4");
+        let analysis = removability(&provided).unwrap();
+        assert_eq!(
+            vec![1, 4, 7, 10, 12, 15, 16, 19],
+            analysis
+                .mandatory
+                .iter()
+                .map(|x| provided.joltages()[*x])
+                .collect_vec()
+        );
+        assert_eq!(
+            vec![
+                Segment {
+                    start: 2,
+                    end: 5,
+                    arrangements: 4
+                },
+                Segment {
+                    start: 6,
+                    end: 8,
+                    arrangements: 2
+                }
+            ],
+            analysis.segments
+        );
+        assert_eq!(Ok(8), analysis.arrangements());
+    }
+
+    #[test]
+    fn it_splits_real_input_into_segments() {
+        let provided = generator(include_str!("../input/2020/day10.txt"));
+        let analysis = removability(&provided).unwrap();
+        assert_eq!(count_arrangements(&provided), analysis.arrangements());
+        for segment in &analysis.segments {
+            assert!(segment.arrangements > 1);
+        }
+        // every adapter is either mandatory or inside exactly one segment
+        let optional: usize = analysis.segments.iter().map(|x| x.end - x.start - 1).sum();
+        assert_eq!(
+            provided.joltages().len() - 2,
+            analysis.mandatory.len() + optional
+        );
+    }
+
+    #[test]
+    fn it_handles_duplicates_and_broken_chains() {
+        let provided = AdapterChain::new(&[1, 2, 2, 3], &ChainConfig::default());
+        let analysis = removability(&provided).unwrap();
+        assert_eq!(vec![4], analysis.mandatory);
+        assert_eq!(count_arrangements(&provided), analysis.arrangements());
+        assert_eq!(Ok(6), analysis.arrangements());
+
+        let provided = AdapterChain::new(&[1, 2, 9], &ChainConfig::default());
+        let analysis = removability(&provided).unwrap();
+        assert!(analysis.mandatory.is_empty());
+        assert_eq!(Ok(0), analysis.arrangements());
+    }
+
+    #[test]
+    fn it_reports_overflowing_segments() {
+        let provided = AdapterChain::new(&(1..=200).collect_vec(), &ChainConfig::default());
+        assert_eq!(Err(Overflow::Add), removability(&provided));
+        // the gap from 100 to 103 splits it into two segments which fit but multiply to too much
+        let provided = AdapterChain::new(
+            &(1..=100).chain(103..=202).collect_vec(),
+            &ChainConfig::default(),
+        );
+        let analysis = removability(&provided).unwrap();
+        assert_eq!(2, analysis.segments.len());
+        assert_eq!(Err(Overflow::Mul), analysis.arrangements());
+        assert_eq!(Err(Overflow::Add), count_arrangements(&provided));
+    }
+
+    #[test]
+    fn it_builds_the_chain() {
+        let chain = generator("16\n10\n15\n5\n1\n11\n7\n19\n6\n12\n4");
+        assert_eq!(
+            &[0, 1, 4, 5, 6, 7, 10, 11, 12, 15, 16, 19, 22],
+            chain.joltages()
+        );
+        assert_eq!(vec![1], chain.successors(chain.outlet_node()));
+        assert_eq!(vec![3, 4, 5], chain.successors(2));
+        assert_eq!(vec![chain.device_node()], chain.successors(11));
+        assert!(chain.successors(chain.device_node()).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "rated above the highest adapter")]
+    fn it_needs_a_device_offset() {
+        AdapterChain::new(
+            &[1, 2],
+            &ChainConfig {
+                device_offset: 0,
+                ..ChainConfig::default()
+            },
+        );
+    }
+
+    #[test]
+    fn it_configures_the_chain() {
+        let config = ChainConfig {
+            max_step: 5,
+            device_offset: 1,
+            outlet: 2,
+        };
+        let chain = AdapterChain::new(&[9, 1, 4, 4, 2, 7], &config);
+        assert_eq!(&[2, 4, 4, 7, 9, 10], chain.joltages());
+        // equal adapters can't be chained but both fit the outlet
+        assert_eq!(vec![1, 2, 3], chain.successors(0));
+        assert_eq!(vec![3, 4], chain.successors(1));
+        assert_eq!(5, chain.device_node());
+        let empty = AdapterChain::new(&[], &ChainConfig::default());
+        assert_eq!(&[0, 3], empty.joltages());
+        assert_eq!(vec![1], empty.successors(0));
+    }
 }
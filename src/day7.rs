@@ -8,13 +8,36 @@ use nom::multi::{many1};
 use nom::IResult;
 
 use std::collections::{HashMap};
+use std::fmt;
+
+pub mod dot;
+pub mod query;
+pub mod random;
+pub mod validate;
 
 /// https://adventofcode.com/2020/day/7
 
 #[derive(Debug, Eq, PartialEq)]
 pub struct Constraint {
-    source: String,
-    contains: Vec<(u32, String)>,
+    pub source: String,
+    pub contains: Vec<(u32, String)>,
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} bags contain ", self.source)?;
+        if self.contains.is_empty() {
+            write!(f, "no other bags")?;
+        }
+        for (index, (amount, colour)) in self.contains.iter().enumerate() {
+            if index > 0 {
+                write!(f, ", ")?;
+            }
+            let plural = if *amount == 1 { "" } else { "s" };
+            write!(f, "{} {} bag{}", amount, colour, plural)?;
+        }
+        write!(f, ".")
+    }
 }
 
 fn bag_constraint(input: &str) -> IResult<&str, Constraint> {
@@ -51,11 +74,13 @@ fn bag_constraint(input: &str) -> IResult<&str, Constraint> {
     ))
 }
 
+pub fn constraints(input: &str) -> Vec<Constraint> {
+    many1(bag_constraint)(input).unwrap().1
+}
+
 #[aoc_generator(day7)]
 pub fn generator(input: &str) -> HashMap<String, Vec<(u32, String)>> {
-    many1(bag_constraint)(input)
-        .unwrap()
-        .1
+    constraints(input)
         .iter()
         .map(|x| (x.source.clone(), x.contains.clone()))
         .collect()
@@ -98,6 +123,7 @@ pub fn solve_part2(input: &HashMap<String, Vec<(u32, String)>>) -> usize {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     #[test]
     fn it_parses_one_line() {
@@ -154,4 +180,42 @@ dark blue bags contain 2 dark violet bags.
 dark violet bags contain no other bags.";
         assert_eq!(126, solve_part2(&generator(provided)));
     }
+
+    #[test]
+    fn it_prints_one_line() {
+        let provided = "light red bags contain 1 bright white bag, 2 muted yellow bags.";
+        assert_eq!(provided, bag_constraint(provided).unwrap().1.to_string());
+        let provided = "faded blue bags contain no other bags.";
+        assert_eq!(provided, bag_constraint(provided).unwrap().1.to_string());
+    }
+
+    #[test]
+    fn it_prints_real_input() {
+        let provided = include_str!("../input/2020/day7.txt");
+        assert_eq!(
+            provided.trim_end(),
+            constraints(provided).iter().map(|x| x.to_string()).join("\n")
+        );
+    }
+
+    fn colour() -> impl Strategy<Value = String> {
+        "[a-z]{1,8} [a-z]{1,8}".prop_filter("words starting with bag confuse the parser", |x| {
+            !x.split(' ').any(|word| word.starts_with("bag"))
+        })
+    }
+
+    fn constraint() -> impl Strategy<Value = Constraint> {
+        (colour(), prop::collection::vec((0u32..1000, colour()), 0..5))
+            .prop_map(|(source, contains)| Constraint { source, contains })
+    }
+
+    proptest! {
+        #[test]
+        fn it_round_trips(rules in prop::collection::vec(constraint(), 1..10)) {
+            let printed = rules.iter().map(|x| x.to_string()).join("\n");
+            prop_assert_eq!(&rules, &constraints(&printed));
+            let reprinted = constraints(&printed).iter().map(|x| x.to_string()).join("\n");
+            prop_assert_eq!(printed, reprinted);
+        }
+    }
 }
@@ -0,0 +1,147 @@
+use itertools::Itertools;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::fmt::Write;
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Direction {
+    /// bags which the chosen colour (transitively) contains
+    Contents,
+    /// bags which (transitively) contain the chosen colour
+    Containers,
+}
+
+fn reachable(edges: &[(&str, u32, &str)], start: &str, direction: Direction) -> HashSet<String> {
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut queue: VecDeque<&str> = VecDeque::from(vec![start]);
+    seen.insert(start.to_string());
+    while let Some(next) = queue.pop_front() {
+        for (from, _, to) in edges {
+            let (here, there) = match direction {
+                Direction::Contents => (from, to),
+                Direction::Containers => (to, from),
+            };
+            if *here == next && seen.insert(there.to_string()) {
+                queue.push_back(there);
+            }
+        }
+    }
+    seen
+}
+
+/// render the rules as a graphviz digraph, optionally restricted to the subgraph around one colour
+pub fn to_dot(
+    input: &HashMap<String, Vec<(u32, String)>>,
+    focus: Option<(&str, Direction)>,
+) -> String {
+    let edges = input
+        .iter()
+        .flat_map(|(source, contains)| {
+            contains
+                .iter()
+                .map(move |(num, colour)| (source.as_str(), *num, colour.as_str()))
+        })
+        .sorted_by_key(|(from, _, to)| (*from, *to))
+        .collect_vec();
+    let keep = focus.map(|(colour, direction)| reachable(&edges, colour, direction));
+    let included = |colour: &str| match &keep {
+        Some(x) => x.contains(colour),
+        None => true,
+    };
+
+    let nodes = input
+        .keys()
+        .map(|x| x.as_str())
+        .chain(edges.iter().map(|(_, _, colour)| *colour))
+        .filter(|x| included(x))
+        .collect::<BTreeSet<_>>();
+
+    let mut out = String::from("digraph bags {\n");
+    for node in nodes {
+        if focus.map(|(colour, _)| colour) == Some(node) {
+            writeln!(out, "    {:?} [style=filled, fillcolor=gold];", node).unwrap();
+        } else {
+            writeln!(out, "    {:?};", node).unwrap();
+        }
+    }
+    for (from, num, to) in edges.iter().filter(|(from, _, to)| included(from) && included(to)) {
+        writeln!(out, "    {:?} -> {:?} [label=\"{}\"];", from, to, num).unwrap();
+    }
+    out.push_str("}\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::generator;
+    use super::*;
+
+    const PROVIDED: &str = "light red bags contain 1 bright white bag, 2 muted yellow bags.
+bright white bags contain 1 shiny gold bag.
+muted yellow bags contain 2 shiny gold bags, 9 faded blue bags.
+shiny gold bags contain 1 dark olive bag.
+dark olive bags contain no other bags.
+faded blue bags contain no other bags.";
+
+    #[test]
+    fn it_exports_everything() {
+        assert_eq!(
+            "digraph bags {
+    \"bright white\";
+    \"dark olive\";
+    \"faded blue\";
+    \"light red\";
+    \"muted yellow\";
+    \"shiny gold\";
+    \"bright white\" -> \"shiny gold\" [label=\"1\"];
+    \"light red\" -> \"bright white\" [label=\"1\"];
+    \"light red\" -> \"muted yellow\" [label=\"2\"];
+    \"muted yellow\" -> \"faded blue\" [label=\"9\"];
+    \"muted yellow\" -> \"shiny gold\" [label=\"2\"];
+    \"shiny gold\" -> \"dark olive\" [label=\"1\"];
+}
+",
+            to_dot(&generator(PROVIDED), None)
+        );
+    }
+
+    #[test]
+    fn it_exports_contents() {
+        assert_eq!(
+            "digraph bags {
+    \"dark olive\";
+    \"faded blue\";
+    \"muted yellow\" [style=filled, fillcolor=gold];
+    \"shiny gold\";
+    \"muted yellow\" -> \"faded blue\" [label=\"9\"];
+    \"muted yellow\" -> \"shiny gold\" [label=\"2\"];
+    \"shiny gold\" -> \"dark olive\" [label=\"1\"];
+}
+",
+            to_dot(
+                &generator(PROVIDED),
+                Some(("muted yellow", Direction::Contents))
+            )
+        );
+    }
+
+    #[test]
+    fn it_exports_containers() {
+        assert_eq!(
+            "digraph bags {
+    \"bright white\";
+    \"light red\";
+    \"muted yellow\";
+    \"shiny gold\" [style=filled, fillcolor=gold];
+    \"bright white\" -> \"shiny gold\" [label=\"1\"];
+    \"light red\" -> \"bright white\" [label=\"1\"];
+    \"light red\" -> \"muted yellow\" [label=\"2\"];
+    \"muted yellow\" -> \"shiny gold\" [label=\"2\"];
+}
+",
+            to_dot(
+                &generator(PROVIDED),
+                Some(("shiny gold", Direction::Containers))
+            )
+        );
+    }
+}
@@ -0,0 +1,296 @@
+use itertools::Itertools;
+use nom::branch::alt;
+use nom::bytes::complete::tag;
+use nom::character::complete::{alpha1, digit1, space0, space1};
+use nom::combinator::{all_consuming, map, map_res, opt, recognize};
+use nom::sequence::{delimited, preceded, separated_pair, tuple};
+use nom::IResult;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// count shiny gold               -> how many bags one shiny gold bag holds
+/// holders shiny gold             -> which bags can (eventually) hold a shiny gold bag
+/// holders at least 3 shiny gold  -> which bags eventually hold 3 or more shiny gold bags
+/// path light red to shiny gold   -> shortest chain of bags from light red to shiny gold
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum Query {
+    Count(String),
+    Holders { colour: String, at_least: u64 },
+    Path { from: String, to: String },
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum Answer {
+    Count(u64),
+    Colours(Vec<String>),
+    Path(Option<Vec<String>>),
+}
+
+impl fmt::Display for Answer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Answer::Count(x) => write!(f, "{}", x),
+            Answer::Colours(x) => write!(f, "{} ({})", x.len(), x.join(", ")),
+            Answer::Path(Some(x)) => write!(f, "{}", x.join(" -> ")),
+            Answer::Path(None) => write!(f, "no path"),
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum QueryError {
+    /// a bag which ends up inside itself, so it holds infinitely many bags
+    Cycle(String),
+    /// the number of bags inside this one doesn't fit in a u64
+    Overflow(String),
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryError::Cycle(x) => write!(f, "{} ends up inside itself", x),
+            QueryError::Overflow(x) => write!(f, "{} holds too many bags to count", x),
+        }
+    }
+}
+
+fn colour(input: &str) -> IResult<&str, String> {
+    map(
+        recognize(separated_pair(alpha1, space1, alpha1)),
+        |x: &str| x.to_string(),
+    )(input)
+}
+
+fn count(input: &str) -> IResult<&str, Query> {
+    map(
+        preceded(tuple((tag("count"), space1)), colour),
+        Query::Count,
+    )(input)
+}
+
+fn holders(input: &str) -> IResult<&str, Query> {
+    let (input, _) = tuple((tag("holders"), space1))(input)?;
+    let (input, at_least) = opt(delimited(
+        tuple((tag("at least"), space1)),
+        map_res(digit1, |x: &str| x.parse::<u64>()),
+        space1,
+    ))(input)?;
+    let (input, colour) = colour(input)?;
+    Ok((
+        input,
+        Query::Holders {
+            colour,
+            at_least: at_least.unwrap_or(1),
+        },
+    ))
+}
+
+fn path(input: &str) -> IResult<&str, Query> {
+    let (input, _) = tuple((tag("path"), space1))(input)?;
+    let (input, (from, to)) =
+        separated_pair(colour, tuple((space1, tag("to"), space1)), colour)(input)?;
+    Ok((input, Query::Path { from, to }))
+}
+
+pub fn parse_query(input: &str) -> Result<Query, String> {
+    all_consuming(delimited(space0, alt((count, holders, path)), space0))(input)
+        .map(|(_, query)| query)
+        .map_err(|_| format!("could not parse query: {}", input))
+}
+
+/// number of `target` bags inside a single `node` bag
+fn count_inside(
+    input: &HashMap<String, Vec<(u32, String)>>,
+    node: &str,
+    target: Option<&str>,
+    memo: &mut HashMap<String, u64>,
+    visiting: &mut HashSet<String>,
+) -> Result<u64, QueryError> {
+    if let Some(x) = memo.get(node) {
+        return Ok(*x);
+    }
+    if !visiting.insert(node.to_string()) {
+        return Err(QueryError::Cycle(node.to_string()));
+    }
+    let mut total: u64 = 0;
+    for (num, child) in input.get(node).into_iter().flatten() {
+        let direct = match target {
+            Some(target) if target != child => 0,
+            _ => 1,
+        };
+        let inside = count_inside(input, child, target, memo, visiting)?;
+        total = inside
+            .checked_add(direct)
+            .and_then(|x| x.checked_mul(*num as u64))
+            .and_then(|x| total.checked_add(x))
+            .ok_or_else(|| QueryError::Overflow(node.to_string()))?;
+    }
+    visiting.remove(node);
+    memo.insert(node.to_string(), total);
+    Ok(total)
+}
+
+/// rules which contain a cycle are reported as an error rather than counted forever, checking them
+/// with `validate::validate` first gives a more useful message
+pub fn evaluate(
+    input: &HashMap<String, Vec<(u32, String)>>,
+    query: &Query,
+) -> Result<Answer, QueryError> {
+    Ok(match query {
+        Query::Count(colour) => Answer::Count(count_inside(
+            input,
+            colour,
+            None,
+            &mut HashMap::new(),
+            &mut HashSet::new(),
+        )?),
+        Query::Holders { colour, at_least } => {
+            let mut memo = HashMap::new();
+            let mut colours = vec![];
+            for x in input.keys().sorted() {
+                if count_inside(input, x, Some(colour), &mut memo, &mut HashSet::new())?
+                    >= *at_least
+                {
+                    colours.push(x.clone());
+                }
+            }
+            Answer::Colours(colours)
+        }
+        Query::Path { from, to } => Answer::Path(pathfinding::directed::bfs::bfs(
+            from,
+            |x| {
+                input
+                    .get(x)
+                    .into_iter()
+                    .flatten()
+                    .map(|(_, child)| child.clone())
+                    .collect_vec()
+            },
+            |x| x == to,
+        )),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::generator;
+    use super::*;
+
+    const PROVIDED: &str = "light red bags contain 1 bright white bag, 2 muted yellow bags.
+dark orange bags contain 3 bright white bags, 4 muted yellow bags.
+bright white bags contain 1 shiny gold bag.
+muted yellow bags contain 2 shiny gold bags, 9 faded blue bags.
+shiny gold bags contain 1 dark olive bag, 2 vibrant plum bags.
+dark olive bags contain 3 faded blue bags, 4 dotted black bags.
+vibrant plum bags contain 5 faded blue bags, 6 dotted black bags.
+faded blue bags contain no other bags.
+dotted black bags contain no other bags.";
+
+    fn ask(query: &str) -> Answer {
+        evaluate(&generator(PROVIDED), &parse_query(query).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn it_parses_queries() {
+        assert_eq!(
+            Ok(Query::Count("shiny gold".to_string())),
+            parse_query("count shiny gold")
+        );
+        assert_eq!(
+            Ok(Query::Holders {
+                colour: "shiny gold".to_string(),
+                at_least: 3
+            }),
+            parse_query("  holders at least 3 shiny gold ")
+        );
+        assert_eq!(
+            Ok(Query::Path {
+                from: "light red".to_string(),
+                to: "shiny gold".to_string()
+            }),
+            parse_query("path light red to shiny gold")
+        );
+        assert!(parse_query("count shiny").is_err());
+        assert!(parse_query("holders at least shiny gold").is_err());
+    }
+
+    #[test]
+    fn it_counts() {
+        assert_eq!(Answer::Count(32), ask("count shiny gold"));
+        assert_eq!(Answer::Count(0), ask("count faded blue"));
+    }
+
+    #[test]
+    fn it_finds_holders() {
+        assert_eq!(
+            Answer::Colours(vec![
+                "bright white".to_string(),
+                "dark orange".to_string(),
+                "light red".to_string(),
+                "muted yellow".to_string()
+            ]),
+            ask("holders shiny gold")
+        );
+        assert_eq!(
+            Answer::Colours(vec!["dark orange".to_string(), "light red".to_string()]),
+            ask("holders at least 3 shiny gold")
+        );
+    }
+
+    #[test]
+    fn it_finds_paths() {
+        assert_eq!(
+            Answer::Path(Some(vec![
+                "light red".to_string(),
+                "bright white".to_string(),
+                "shiny gold".to_string()
+            ])),
+            ask("path light red to shiny gold")
+        );
+        assert_eq!(Answer::Path(None), ask("path shiny gold to light red"));
+    }
+
+    #[test]
+    fn it_agrees_with_solutions() {
+        let provided = generator(include_str!("../../input/2020/day7.txt"));
+        let query = parse_query("holders shiny gold").unwrap();
+        match evaluate(&provided, &query).unwrap() {
+            Answer::Colours(x) => assert_eq!(super::super::solve_part1(&provided), x.len()),
+            x => panic!("unexpected answer {:?}", x),
+        }
+        assert_eq!(
+            Answer::Count(super::super::solve_part2(&provided) as u64),
+            evaluate(&provided, &parse_query("count shiny gold").unwrap()).unwrap()
+        );
+    }
+
+    #[test]
+    fn it_reports_cycles_and_overflow() {
+        let provided = generator(
+            "light red bags contain 1 bright white bag.
+bright white bags contain 2 light red bags.",
+        );
+        assert_eq!(
+            Err(QueryError::Cycle("light red".to_string())),
+            evaluate(&provided, &parse_query("count light red").unwrap())
+        );
+        assert!(evaluate(
+            &provided,
+            &parse_query("path light red to bright white").unwrap()
+        )
+        .is_ok());
+
+        // every level multiplies by 4294967295, so the third one no longer fits
+        let provided = generator(
+            "light red bags contain 4294967295 bright white bags.
+bright white bags contain 4294967295 muted yellow bags.
+muted yellow bags contain 4294967295 faded blue bags.
+faded blue bags contain no other bags.",
+        );
+        assert_eq!(
+            Err(QueryError::Overflow("light red".to_string())),
+            evaluate(&provided, &parse_query("count light red").unwrap())
+        );
+        assert!(evaluate(&provided, &parse_query("count bright white").unwrap()).is_ok());
+    }
+}
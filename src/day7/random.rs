@@ -0,0 +1,225 @@
+use super::Constraint;
+use itertools::Itertools;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+const ADJECTIVES: [&str; 16] = [
+    "light", "dark", "bright", "muted", "shiny", "faded", "dotted", "vibrant", "dull", "clear",
+    "pale", "posh", "wavy", "plaid", "striped", "drab",
+];
+
+const COLOURS: [&str; 16] = [
+    "red", "orange", "white", "yellow", "gold", "olive", "plum", "blue", "black", "green", "teal",
+    "violet", "cyan", "maroon", "lime", "salmon",
+];
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct RuleSetConfig {
+    pub colours: usize,
+    /// maximum number of distinct colours directly inside one bag
+    pub fan_out: usize,
+    /// number of containment levels below the outermost bags
+    pub depth: usize,
+    pub max_amount: u32,
+    pub target: String,
+    pub seed: u64,
+}
+
+impl Default for RuleSetConfig {
+    fn default() -> Self {
+        RuleSetConfig {
+            colours: 600,
+            fan_out: 4,
+            depth: 8,
+            max_amount: 5,
+            target: "shiny gold".to_string(),
+            seed: 0,
+        }
+    }
+}
+
+fn colour_name(index: usize) -> String {
+    let adjective = ADJECTIVES[index % ADJECTIVES.len()];
+    let mut colour = COLOURS[(index / ADJECTIVES.len()) % COLOURS.len()].to_string();
+    let mut suffix = index / (ADJECTIVES.len() * COLOURS.len());
+    while suffix > 0 {
+        colour.push((b'a' + (suffix % 26) as u8) as char);
+        suffix /= 26;
+    }
+    format!("{} {}", adjective, colour)
+}
+
+/// acyclic rules where the first `depth + 1` colours form a chain running through `target`
+pub fn random_rules(config: &RuleSetConfig) -> Vec<Constraint> {
+    assert!(
+        config.colours > config.depth,
+        "need at least one colour per level"
+    );
+    assert!(
+        config.fan_out > 0,
+        "the chain needs room for one colour inside each bag"
+    );
+    assert!(
+        config.depth > 0,
+        "the target needs at least one bag above it"
+    );
+    assert!(
+        config.max_amount > 0,
+        "every bag inside another needs an amount of at least 1"
+    );
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let names = std::iter::once(config.target.clone())
+        .chain((0..).map(colour_name).filter(|x| *x != config.target))
+        .take(config.colours)
+        .collect_vec();
+
+    // the chain occupies one colour per level with the target halfway down, rounded up so
+    // there is always something above it
+    let target_level = config.depth.div_ceil(2);
+    let mut chain = (1..=config.depth).collect_vec();
+    chain.insert(target_level, 0);
+    let mut levels = vec![0; config.colours];
+    for (level, colour) in chain.iter().enumerate() {
+        levels[*colour] = level;
+    }
+    for level in levels.iter_mut().skip(config.depth + 1) {
+        *level = rng.gen_range(0..=config.depth);
+    }
+
+    // sorted by level, so everything below a level is the tail starting at `deeper[level]`
+    let by_level = (0..config.colours)
+        .sorted_by_key(|x| levels[*x])
+        .collect_vec();
+    let deeper = (0..=config.depth)
+        .map(|level| by_level.partition_point(|x| levels[*x] <= level))
+        .collect_vec();
+
+    let mut rules = (0..config.colours)
+        .map(|colour| {
+            let below = &by_level[deeper[levels[colour]]..];
+            let amount = rng.gen_range(0..=config.fan_out);
+            let mut children = below
+                .choose_multiple(&mut rng, amount)
+                .cloned()
+                .collect_vec();
+            if let Some(next) = chain.get(levels[colour] + 1) {
+                if chain[levels[colour]] == colour && !children.contains(next) {
+                    children.truncate(config.fan_out - 1);
+                    children.push(*next);
+                }
+            }
+            Constraint {
+                source: names[colour].clone(),
+                contains: children
+                    .into_iter()
+                    .map(|x| (rng.gen_range(1..=config.max_amount), names[x].clone()))
+                    .collect(),
+            }
+        })
+        .collect_vec();
+    rules.shuffle(&mut rng);
+    rules
+}
+
+/// text in the puzzle input format, accepted by `day7::generator`
+pub fn random_input(config: &RuleSetConfig) -> String {
+    random_rules(config)
+        .iter()
+        .map(|x| x.to_string())
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::validate::validate;
+    use super::super::{constraints, generator, solve_part1, solve_part2};
+    use super::*;
+
+    #[test]
+    fn it_is_deterministic() {
+        let config = RuleSetConfig::default();
+        assert_eq!(random_input(&config), random_input(&config));
+        assert_ne!(
+            random_input(&config),
+            random_input(&RuleSetConfig { seed: 1, ..config })
+        );
+    }
+
+    #[test]
+    fn it_generates_valid_rules() {
+        for seed in 0..10 {
+            let config = RuleSetConfig {
+                seed,
+                ..RuleSetConfig::default()
+            };
+            let provided = random_input(&config);
+            assert_eq!(config.colours, constraints(&provided).len());
+            assert!(validate(&constraints(&provided)).is_empty());
+            assert_eq!(config.colours, generator(&provided).len());
+        }
+    }
+
+    #[test]
+    fn it_reaches_the_target() {
+        for seed in 0..10 {
+            let config = RuleSetConfig {
+                colours: 50,
+                fan_out: 1,
+                depth: 6,
+                seed,
+                ..RuleSetConfig::default()
+            };
+            let rules = generator(&random_input(&config));
+            assert!(solve_part1(&rules) >= 3);
+            assert!(solve_part2(&rules) >= 3);
+        }
+    }
+
+    #[test]
+    fn it_respects_fan_out() {
+        let config = RuleSetConfig {
+            fan_out: 2,
+            ..RuleSetConfig::default()
+        };
+        assert!(random_rules(&config).iter().all(|x| x.contains.len() <= 2));
+    }
+
+    #[test]
+    fn it_puts_a_bag_above_the_target() {
+        let config = RuleSetConfig {
+            colours: 2,
+            depth: 1,
+            ..RuleSetConfig::default()
+        };
+        let rules = generator(&random_input(&config));
+        assert_eq!(1, solve_part1(&rules));
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one bag above it")]
+    fn it_needs_some_depth() {
+        random_rules(&RuleSetConfig {
+            depth: 0,
+            ..RuleSetConfig::default()
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "amount of at least 1")]
+    fn it_needs_some_amount() {
+        random_rules(&RuleSetConfig {
+            max_amount: 0,
+            ..RuleSetConfig::default()
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "room for one colour")]
+    fn it_needs_some_fan_out() {
+        random_rules(&RuleSetConfig {
+            fan_out: 0,
+            ..RuleSetConfig::default()
+        });
+    }
+}
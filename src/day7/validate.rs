@@ -0,0 +1,165 @@
+use super::Constraint;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum RuleError {
+    /// path of colours which starts and ends with the same bag
+    Cycle(Vec<String>),
+    /// `source` contains `colour` but there is no rule for `colour`
+    Dangling { source: String, colour: String },
+    /// more than one rule for the same colour
+    Duplicate(String),
+}
+
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuleError::Cycle(path) => write!(f, "cycle: {}", path.join(" -> ")),
+            RuleError::Dangling { source, colour } => {
+                write!(f, "{} contains {} which has no rule", source, colour)
+            }
+            RuleError::Duplicate(colour) => write!(f, "{} has more than one rule", colour),
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+enum Visit {
+    OnStack,
+    Done,
+}
+
+fn find_cycles<'a>(
+    node: &'a str,
+    graph: &HashMap<&'a str, Vec<&'a str>>,
+    state: &mut HashMap<&'a str, Visit>,
+    stack: &mut Vec<&'a str>,
+    cycles: &mut Vec<Vec<String>>,
+) {
+    state.insert(node, Visit::OnStack);
+    stack.push(node);
+    for child in graph.get(node).into_iter().flatten() {
+        match state.get(child) {
+            Some(Visit::OnStack) => {
+                let start = stack.iter().position(|x| x == child).unwrap();
+                cycles.push(
+                    stack[start..]
+                        .iter()
+                        .chain(std::iter::once(child))
+                        .map(|x| x.to_string())
+                        .collect(),
+                );
+            }
+            Some(Visit::Done) => {}
+            None => find_cycles(child, graph, state, stack, cycles),
+        }
+    }
+    stack.pop();
+    state.insert(node, Visit::Done);
+}
+
+/// check parsed rules for duplicate definitions, colours without a rule and bags which contain themselves
+pub fn validate(rules: &[Constraint]) -> Vec<RuleError> {
+    let mut errors = vec![];
+    let mut graph: HashMap<&str, Vec<&str>> = HashMap::new();
+    let mut duplicates: HashSet<&str> = HashSet::new();
+    for rule in rules {
+        if graph.contains_key(rule.source.as_str()) && duplicates.insert(&rule.source) {
+            errors.push(RuleError::Duplicate(rule.source.clone()));
+        }
+        graph
+            .entry(&rule.source)
+            .or_default()
+            .extend(rule.contains.iter().map(|(_, colour)| colour.as_str()));
+    }
+
+    let mut dangling: HashSet<(&str, &str)> = HashSet::new();
+    for rule in rules {
+        for (_, colour) in &rule.contains {
+            if !graph.contains_key(colour.as_str()) && dangling.insert((&rule.source, colour)) {
+                errors.push(RuleError::Dangling {
+                    source: rule.source.clone(),
+                    colour: colour.clone(),
+                });
+            }
+        }
+    }
+
+    let mut state = HashMap::new();
+    let mut cycles = vec![];
+    for rule in rules {
+        if !state.contains_key(rule.source.as_str()) {
+            find_cycles(&rule.source, &graph, &mut state, &mut vec![], &mut cycles);
+        }
+    }
+    errors.extend(cycles.into_iter().map(RuleError::Cycle));
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::constraints;
+    use super::*;
+
+    #[test]
+    fn it_accepts_valid_rules() {
+        let provided = "light red bags contain 1 bright white bag, 2 muted yellow bags.
+dark orange bags contain 3 bright white bags, 4 muted yellow bags.
+bright white bags contain 1 shiny gold bag.
+muted yellow bags contain 2 shiny gold bags, 9 faded blue bags.
+shiny gold bags contain 1 dark olive bag, 2 vibrant plum bags.
+dark olive bags contain 3 faded blue bags, 4 dotted black bags.
+vibrant plum bags contain 5 faded blue bags, 6 dotted black bags.
+faded blue bags contain no other bags.
+dotted black bags contain no other bags.";
+        assert_eq!(Vec::<RuleError>::new(), validate(&constraints(provided)));
+    }
+
+    #[test]
+    fn it_accepts_real_input() {
+        let provided = include_str!("../../input/2020/day7.txt");
+        assert_eq!(Vec::<RuleError>::new(), validate(&constraints(provided)));
+    }
+
+    #[test]
+    fn it_finds_cycles() {
+        let provided = "shiny gold bags contain 2 dark red bags.
+dark red bags contain 2 dark orange bags.
+dark orange bags contain 1 shiny gold bag, 3 dark blue bags.
+dark blue bags contain 1 dark blue bag.";
+        assert_eq!(
+            vec![
+                RuleError::Cycle(vec![
+                    "shiny gold".to_string(),
+                    "dark red".to_string(),
+                    "dark orange".to_string(),
+                    "shiny gold".to_string()
+                ]),
+                RuleError::Cycle(vec!["dark blue".to_string(), "dark blue".to_string()]),
+            ],
+            validate(&constraints(provided))
+        );
+    }
+
+    #[test]
+    fn it_finds_dangling_and_duplicates() {
+        let provided = "shiny gold bags contain 2 dark red bags, 1 faded blue bag.
+dark red bags contain no other bags.
+dark red bags contain 3 faded blue bags.";
+        assert_eq!(
+            vec![
+                RuleError::Duplicate("dark red".to_string()),
+                RuleError::Dangling {
+                    source: "shiny gold".to_string(),
+                    colour: "faded blue".to_string()
+                },
+                RuleError::Dangling {
+                    source: "dark red".to_string(),
+                    colour: "faded blue".to_string()
+                },
+            ],
+            validate(&constraints(provided))
+        );
+    }
+}
@@ -1,110 +1,355 @@
-use nom::bytes::complete::{tag, take_until, take_while};
-use nom::character::complete::multispace0;
-use nom::combinator::opt;
-use std::collections::HashSet;
-use nom::multi::many1;
+use nom::character::complete::{alpha1, digit1, one_of, space1};
+use nom::combinator::{all_consuming, map_res, opt, recognize};
+use nom::sequence::pair;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
 
 use nom::IResult;
 
+pub mod asm;
+pub mod cfg;
+pub mod debugger;
+pub mod disasm;
+pub mod optimize;
+pub mod random;
+pub mod repair;
+pub mod symbolic;
+pub mod trace;
+
 /// https://adventofcode.com/2020/day/8
 
-#[derive(Debug, Eq, PartialEq, Clone)]
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
 pub enum Operation {
     Nop(isize),
     Acc(isize),
     Jmp(isize),
+    /// multiply acc by the parameter
+    Mul(isize),
+    /// jump only if acc is zero
+    Jz(isize),
+    /// append acc to the output, the parameter is ignored
+    Out(isize),
+    /// store acc in the register named by the parameter
+    Sto(isize),
+    /// load the register named by the parameter into acc
+    Lod(isize),
 }
 
+pub type Opcode = (&'static str, fn(isize) -> Operation);
+
+/// every mnemonic the parser understands, new instructions also need arms in `param` and `Machine::step`
+pub const OPCODES: &[Opcode] = &[
+    ("nop", Operation::Nop),
+    ("acc", Operation::Acc),
+    ("jmp", Operation::Jmp),
+    ("mul", Operation::Mul),
+    ("jz", Operation::Jz),
+    ("out", Operation::Out),
+    ("sto", Operation::Sto),
+    ("lod", Operation::Lod),
+];
+
 impl Operation {
+    pub fn from_mnemonic(mnemonic: &str, param: isize) -> Option<Self> {
+        OPCODES
+            .iter()
+            .find(|(name, _)| *name == mnemonic)
+            .map(|(_, build)| build(param))
+    }
+    pub fn mnemonic(&self) -> &'static str {
+        OPCODES
+            .iter()
+            .find(|(_, build)| build(self.param()) == *self)
+            .map(|(name, _)| *name)
+            .unwrap()
+    }
+    pub fn param(&self) -> isize {
+        match self {
+            Operation::Nop(x)
+            | Operation::Acc(x)
+            | Operation::Jmp(x)
+            | Operation::Mul(x)
+            | Operation::Jz(x)
+            | Operation::Out(x)
+            | Operation::Sto(x)
+            | Operation::Lod(x) => *x,
+        }
+    }
     pub fn is_acc(&self) -> bool {
         match self {
             Operation::Acc(_) => true,
             _ => false,
         }
     }
+    /// addresses control can move to after executing this instruction at `index`
+    pub fn successors(&self, index: usize) -> Vec<isize> {
+        let index = index as isize;
+        match self {
+            Operation::Jmp(x) => vec![index.saturating_add(*x)],
+            Operation::Jz(x) => vec![index + 1, index.saturating_add(*x)],
+            _ => vec![index + 1],
+        }
+    }
     pub fn flip(&self) -> Self {
         match self {
             Operation::Nop(x) => Operation::Jmp(*x),
             Operation::Jmp(x) => Operation::Nop(*x),
-            Operation::Acc(x) => Operation::Acc(*x),
+            x => *x,
         }
     }
 }
 
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {:+}", self.mnemonic(), self.param())
+    }
+}
+
 #[derive(Debug, Eq, PartialEq, Clone)]
-pub struct ExecutionReport {
-    halting: bool,
-    acc: isize,
-    visited: HashSet<usize>,
+pub enum ParseError {
+    UnknownMnemonic { line: usize, mnemonic: String },
+    Malformed { line: usize, text: String },
 }
 
-fn operation(input: &str) -> IResult<&str, Operation> {
-    let (input, op) = take_until(" ")(input)?;
-    let (input, _) = multispace0(input)?;
-    let (input, param) = take_while(|x: char| x == '-' || x == '+' || x.is_digit(10))(input)?;
-    let (input, _) = opt(tag("\n"))(input)?;
-    let param = param.parse::<isize>().unwrap();
-    match op {
-        "nop" => Ok((input, Operation::Nop(param))),
-        "acc" => Ok((input, Operation::Acc(param))),
-        "jmp" => Ok((input, Operation::Jmp(param))),
-        _ => unreachable!(),
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::UnknownMnemonic { line, mnemonic } => {
+                write!(f, "line {}: unknown mnemonic {}", line, mnemonic)
+            }
+            ParseError::Malformed { line, text } => {
+                write!(f, "line {}: can't parse {:?}", line, text)
+            }
+        }
     }
 }
 
-#[aoc_generator(day8)]
-pub fn generator(input: &str) -> Vec<Operation> {
-    many1(operation)(input).unwrap().1
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct ExecutionReport {
+    pub termination: Termination,
+    pub acc: isize,
+    pub visited: HashSet<usize>,
 }
 
-fn execute_from(input: &[Operation], base: usize) -> ExecutionReport {
-    let mut rip = base;
-    let mut acc = 0;
-    let mut visited: HashSet<usize> = HashSet::new();
-    while rip < input.len() {
-        if !visited.insert(rip) {
-            return ExecutionReport {
-                acc: acc,
-                halting: true,
-                visited,
-            };
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Termination {
+    /// can never halt, see `Machine::status`
+    Looped,
+    /// fell off the end onto the instruction right after the last one
+    Halted,
+    /// jumped further than one past the last instruction
+    JumpedPastEnd,
+    /// jumped to before the first instruction
+    JumpedBeforeStart,
+    /// still running after `Machine::step_limit` instructions
+    StepLimit,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum StepOutcome {
+    /// the next instruction hasn't been executed yet
+    Continued,
+    /// the machine is going round the same loop forever
+    Looped,
+    /// rip is exactly one past the last instruction
+    Halted,
+    /// rip jumped somewhere outside of the program
+    OutOfBounds,
+    /// `step_limit` instructions have been executed without stopping
+    StepLimit,
+}
+
+/// far more than a program without `jz` can run, which stops within one pass over its instructions
+pub const STEP_LIMIT: usize = 100_000;
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Machine {
+    pub program: Vec<Operation>,
+    pub rip: isize,
+    pub acc: isize,
+    pub registers: HashMap<isize, isize>,
+    pub output: Vec<isize>,
+    /// a loop through a `jz` can go on until acc wraps round, so give up after this many steps
+    pub step_limit: usize,
+    visited: HashSet<usize>,
+    /// step at which each address was last executed
+    last_visit: HashMap<usize, usize>,
+    /// step at which the last `jz` which could go two ways was executed
+    last_branch: Option<usize>,
+    /// rip, acc and registers every time such a `jz` was about to execute
+    branches: HashSet<(isize, isize, BTreeMap<isize, isize>)>,
+    steps: usize,
+}
+
+impl Machine {
+    pub fn new(program: Vec<Operation>) -> Self {
+        Machine::starting_at(program, 0)
+    }
+
+    pub fn starting_at(program: Vec<Operation>, rip: usize) -> Self {
+        Machine {
+            program,
+            rip: rip as isize,
+            acc: 0,
+            registers: HashMap::new(),
+            output: vec![],
+            step_limit: STEP_LIMIT,
+            visited: HashSet::new(),
+            last_visit: HashMap::new(),
+            last_branch: None,
+            branches: HashSet::new(),
+            steps: 0,
+        }
+    }
+
+    pub fn visited(&self) -> &HashSet<usize> {
+        &self.visited
+    }
+
+    fn is_branch(op: Operation) -> bool {
+        matches!(op, Operation::Jz(x) if x != 1)
+    }
+
+    fn branch_state(&self) -> (isize, isize, BTreeMap<isize, isize>) {
+        let registers = self.registers.iter().map(|(k, v)| (*k, *v)).collect();
+        (self.rip, self.acc, registers)
+    }
+
+    /// the machine has looped when it gets back to an address without passing a `jz` since the last
+    /// time, as nothing can make it go another way, or when it is about to take a `jz` in exactly
+    /// the same state as before
+    ///
+    /// without `jz` that is simply the first instruction to run twice, with it a loop which keeps
+    /// changing acc would run until acc comes round to the same value, so it stops at `step_limit`
+    pub fn status(&self) -> StepOutcome {
+        if self.rip < 0 || self.rip as usize > self.program.len() {
+            return StepOutcome::OutOfBounds;
+        } else if self.rip as usize == self.program.len() {
+            return StepOutcome::Halted;
         }
-        match input[rip] {
-            Operation::Nop(_) => {}
-            Operation::Acc(param) => acc += param,
-            Operation::Jmp(param) => {
-                if param.is_positive() {
-                    rip += param as usize
-                } else {
-                    rip -= param.abs() as usize
+        let rip = self.rip as usize;
+        let repeats = match (self.last_visit.get(&rip), self.last_branch) {
+            (None, _) => false,
+            (Some(_), None) => true,
+            (Some(visit), Some(branch)) => *visit > branch,
+        };
+        if repeats
+            || (Machine::is_branch(self.program[rip])
+                && self.branches.contains(&self.branch_state()))
+        {
+            StepOutcome::Looped
+        } else if self.steps >= self.step_limit {
+            StepOutcome::StepLimit
+        } else {
+            StepOutcome::Continued
+        }
+    }
+
+    /// execute one instruction, unless the machine has already stopped
+    pub fn step(&mut self) -> StepOutcome {
+        let status = self.status();
+        if status != StepOutcome::Continued {
+            return status;
+        }
+        self.execute();
+        self.status()
+    }
+
+    /// execute the instruction at rip even if it has been executed before, rip must be in bounds
+    pub fn execute(&mut self) {
+        let rip = self.rip as usize;
+        self.visited.insert(rip);
+        self.last_visit.insert(rip, self.steps);
+        if Machine::is_branch(self.program[rip]) {
+            self.branches.insert(self.branch_state());
+            self.last_branch = Some(self.steps);
+        }
+        self.steps += 1;
+        match self.program[rip] {
+            Operation::Jmp(param) => self.rip = self.rip.saturating_add(param),
+            Operation::Jz(param) if self.acc == 0 => self.rip = self.rip.saturating_add(param),
+            op => {
+                match op {
+                    Operation::Acc(param) => self.acc = self.acc.wrapping_add(param),
+                    Operation::Mul(param) => self.acc = self.acc.wrapping_mul(param),
+                    Operation::Out(_) => self.output.push(self.acc),
+                    Operation::Sto(register) => {
+                        self.registers.insert(register, self.acc);
+                    }
+                    Operation::Lod(register) => {
+                        self.acc = self.registers.get(&register).cloned().unwrap_or(0)
+                    }
+                    Operation::Nop(_) | Operation::Jmp(_) | Operation::Jz(_) => {}
                 }
-                continue;
+                self.rip += 1;
             }
         }
+    }
 
-        rip += 1;
+    /// step until the machine stops or `predicate` holds before the next instruction
+    pub fn run_until<F: FnMut(&Machine) -> bool>(&mut self, mut predicate: F) -> StepOutcome {
+        loop {
+            let status = self.status();
+            if status != StepOutcome::Continued || predicate(self) {
+                return status;
+            }
+            self.step();
+        }
+    }
+
+    pub fn run(&mut self) -> StepOutcome {
+        self.run_until(|_| false)
     }
+}
+
+fn operation(input: &str) -> IResult<&str, (&str, isize)> {
+    let (input, op) = alpha1(input)?;
+    let (input, _) = space1(input)?;
+    let (input, param) = map_res(recognize(pair(opt(one_of("+-")), digit1)), |x: &str| {
+        x.parse::<isize>()
+    })(input)?;
+    Ok((input, (op, param)))
+}
+
+pub fn parse_program(input: &str) -> Result<Vec<Operation>, ParseError> {
+    input
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            let (_, (mnemonic, param)) =
+                all_consuming(operation)(line.trim()).map_err(|_| ParseError::Malformed {
+                    line: index + 1,
+                    text: line.to_string(),
+                })?;
+            Operation::from_mnemonic(mnemonic, param).ok_or_else(|| ParseError::UnknownMnemonic {
+                line: index + 1,
+                mnemonic: mnemonic.to_string(),
+            })
+        })
+        .collect()
+}
+
+#[aoc_generator(day8)]
+pub fn generator(input: &str) -> Vec<Operation> {
+    parse_program(input).unwrap()
+}
+
+fn execute_from(input: &[Operation], base: usize) -> ExecutionReport {
+    let mut machine = Machine::starting_at(input.to_vec(), base);
+    let termination = match machine.run() {
+        StepOutcome::Looped => Termination::Looped,
+        StepOutcome::Halted => Termination::Halted,
+        StepOutcome::OutOfBounds if machine.rip < 0 => Termination::JumpedBeforeStart,
+        StepOutcome::OutOfBounds => Termination::JumpedPastEnd,
+        StepOutcome::StepLimit => Termination::StepLimit,
+        StepOutcome::Continued => unreachable!(),
+    };
     ExecutionReport {
-        acc,
-        halting: false,
-        visited,
-    }
-}
-
-fn find_halting(input: &[Operation]) -> HashSet<usize> {
-    let mut unvisited: HashSet<usize> = (0..input.len())
-        .into_iter()
-        .filter(|x| !input[*x].is_acc())
-        .collect();
-    let mut halting: HashSet<usize> = HashSet::new();
-    while let Some(next) = unvisited.iter().next().cloned() {
-        unvisited.remove(&next);
-        let report = execute_from(input, next);
-        if report.halting {
-            halting = halting.union(&report.visited).cloned().collect()
-        }
+        acc: machine.acc,
+        termination,
+        visited: machine.visited,
     }
-    halting
 }
 
 #[aoc(day8, part1)]
@@ -113,21 +358,7 @@ pub fn solve_part1(input: &[Operation]) -> isize {
 }
 #[aoc(day8, part2)]
 pub fn solve_part2(input: &[Operation]) -> isize {
-    let halting = find_halting(input);
-    let (fixed_program, _) = input
-        .iter()
-        .map(|x| x.flip())
-        .enumerate()
-        .filter(|(index, _)| halting.contains(index))
-        .map(|(index, x)| {
-            let mut fixed_program = input.to_vec();
-            fixed_program[index] = x;
-            let report = execute_from(&fixed_program, 0);
-            (fixed_program, report)
-        })
-        .find(|(_, x)| !x.halting)
-        .unwrap();
-    execute_from(&fixed_program, 0).acc
+    repair::repair(input).unwrap().acc
 }
 
 #[cfg(test)]
@@ -181,4 +412,169 @@ acc +6";
         let provided = include_str!("../input/2020/day8.txt");
         assert_eq!(1245, solve_part2(&generator(provided)));
     }
+
+    #[test]
+    fn it_steps() {
+        let provided = "nop +0
+acc +1
+jmp +4
+acc +3
+jmp -3
+acc -99
+acc +1
+jmp -4
+acc +6";
+        let mut machine = Machine::new(generator(provided));
+        assert_eq!(StepOutcome::Continued, machine.step());
+        assert_eq!(StepOutcome::Continued, machine.step());
+        assert_eq!((2, 1), (machine.rip, machine.acc));
+        assert_eq!(StepOutcome::Continued, machine.step());
+        assert_eq!((6, 1), (machine.rip, machine.acc));
+        assert_eq!(StepOutcome::Looped, machine.run());
+        assert_eq!((1, 5), (machine.rip, machine.acc));
+        assert_eq!(StepOutcome::Looped, machine.step());
+        assert_eq!((1, 5), (machine.rip, machine.acc));
+    }
+
+    #[test]
+    fn it_runs_until() {
+        let provided = "acc +1
+acc +2
+acc +3
+nop +0";
+        let mut machine = Machine::new(generator(provided));
+        assert_eq!(StepOutcome::Continued, machine.run_until(|x| x.acc >= 3));
+        assert_eq!((2, 3), (machine.rip, machine.acc));
+        assert_eq!(StepOutcome::Continued, machine.run_until(|x| x.acc >= 3));
+        assert_eq!((2, 3), (machine.rip, machine.acc));
+        assert_eq!(StepOutcome::Halted, machine.run_until(|x| x.acc > 6));
+        assert_eq!((4, 6), (machine.rip, machine.acc));
+    }
+
+    #[test]
+    fn it_stops_out_of_bounds() {
+        let mut machine = Machine::new(generator("nop +0\njmp +5\nacc +1"));
+        assert_eq!(StepOutcome::OutOfBounds, machine.run());
+        assert_eq!(6, machine.rip);
+        let mut machine = Machine::new(generator("acc +1\njmp -2"));
+        assert_eq!(StepOutcome::OutOfBounds, machine.run());
+        assert_eq!((-1, 1), (machine.rip, machine.acc));
+    }
+
+    #[test]
+    fn it_reports_termination() {
+        let report = execute_from(&generator("acc +1\njmp +2\nacc +2\nacc +3"), 0);
+        assert_eq!((Termination::Halted, 4), (report.termination, report.acc));
+        let report = execute_from(&generator("acc +1\njmp -1"), 0);
+        assert_eq!((Termination::Looped, 1), (report.termination, report.acc));
+    }
+
+    #[test]
+    fn it_reports_jumps_past_end() {
+        let report = execute_from(&generator("acc +1\njmp +3\nacc +2"), 0);
+        assert_eq!(
+            (Termination::JumpedPastEnd, 1),
+            (report.termination, report.acc)
+        );
+        let report = execute_from(&generator("jmp +1000"), 0);
+        assert_eq!(Termination::JumpedPastEnd, report.termination);
+    }
+
+    #[test]
+    fn it_reports_jumps_before_start() {
+        let report = execute_from(&generator("acc +1\njmp -2\nacc +2"), 0);
+        assert_eq!(
+            (Termination::JumpedBeforeStart, 1),
+            (report.termination, report.acc)
+        );
+        let report = execute_from(&generator("nop +0\njmp -1000"), 1);
+        assert_eq!(Termination::JumpedBeforeStart, report.termination);
+    }
+
+    #[test]
+    fn it_rejects_unknown_mnemonics() {
+        assert_eq!(
+            Err(ParseError::UnknownMnemonic {
+                line: 2,
+                mnemonic: "hcf".to_string()
+            }),
+            parse_program("nop +0\nhcf +1\nacc +1")
+        );
+        assert_eq!(
+            Err(ParseError::Malformed {
+                line: 1,
+                text: "acc".to_string()
+            }),
+            parse_program("acc")
+        );
+    }
+
+    #[test]
+    fn it_prints_operations() {
+        let provided = include_str!("../input/2020/day8.txt");
+        assert_eq!(
+            provided.trim_end(),
+            generator(provided)
+                .iter()
+                .map(|x| x.to_string())
+                .collect::<Vec<_>>()
+                .join("\n")
+        );
+    }
+
+    #[test]
+    fn it_runs_extended_instructions() {
+        let provided = "acc +6
+sto +1
+mul +7
+out +0
+lod +1
+out +0
+lod +2
+out +0";
+        let mut machine = Machine::new(generator(provided));
+        assert_eq!(StepOutcome::Halted, machine.run());
+        assert_eq!(vec![42, 6, 0], machine.output);
+        assert_eq!(6, machine.registers[&1]);
+    }
+
+    #[test]
+    fn it_jumps_if_zero() {
+        let mut machine = Machine::new(generator(
+            "jz +2\nout +0\nacc +3\njz +2\nout +0\nmul -2\nout +0",
+        ));
+        assert_eq!(StepOutcome::Halted, machine.run());
+        assert_eq!(vec![3, -6], machine.output);
+    }
+
+    #[test]
+    fn it_runs_loops_which_depend_on_acc() {
+        // counts acc down from 3 and leaves the loop once it reaches zero
+        let provided = generator("acc +3\nacc -1\njz +2\njmp -2\nout +0");
+        let mut machine = Machine::new(provided.clone());
+        assert_eq!(StepOutcome::Halted, machine.run());
+        assert_eq!(vec![0], machine.output);
+        let report = execute_from(&provided, 0);
+        assert_eq!((Termination::Halted, 0), (report.termination, report.acc));
+
+        // acc goes 1, 0, 1, 0, ... so the jz sees the same state again
+        let report = execute_from(&generator("acc +1\nmul -1\nacc +1\njz -2\njmp -3"), 0);
+        assert_eq!(Termination::Looped, report.termination);
+        // a loop with a jz which can only go one way is still caught the first time round
+        let report = execute_from(&generator("acc +1\njz +1\njmp -2"), 0);
+        assert_eq!((Termination::Looped, 1), (report.termination, report.acc));
+    }
+
+    #[test]
+    fn it_gives_up_on_long_loops() {
+        // acc has to wrap all the way round before the jz is taken
+        let provided = generator("acc +1\njz +2\njmp -2\nnop +0");
+        let report = execute_from(&provided, 0);
+        assert_eq!(Termination::StepLimit, report.termination);
+        let mut machine = Machine::new(provided);
+        machine.step_limit = 10;
+        assert_eq!(StepOutcome::StepLimit, machine.run());
+        assert_eq!(4, machine.acc);
+        assert_eq!(StepOutcome::StepLimit, machine.step());
+    }
 }
@@ -0,0 +1,211 @@
+use super::Operation;
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum AsmError {
+    UnknownMnemonic { line: usize, mnemonic: String },
+    UnknownLabel { line: usize, label: String },
+    DuplicateLabel { line: usize, label: String },
+    Malformed { line: usize, text: String },
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AsmError::UnknownMnemonic { line, mnemonic } => {
+                write!(f, "line {}: unknown mnemonic {}", line, mnemonic)
+            }
+            AsmError::UnknownLabel { line, label } => {
+                write!(f, "line {}: unknown label {}", line, label)
+            }
+            AsmError::DuplicateLabel { line, label } => {
+                write!(f, "line {}: label {} is already defined", line, label)
+            }
+            AsmError::Malformed { line, text } => {
+                write!(f, "line {}: can't parse {:?}", line, text)
+            }
+        }
+    }
+}
+
+fn is_identifier(x: &str) -> bool {
+    let mut chars = x.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+/// turn source with `name:` labels and `#` or `;` comments into the relative offsets the vm uses
+///
+/// an operand which names a label becomes the distance from the instruction to that label, a label
+/// after the last instruction points one past the end so jumping to it halts
+pub fn assemble(input: &str) -> Result<Vec<Operation>, AsmError> {
+    let mut labels: HashMap<&str, usize> = HashMap::new();
+    let mut instructions: Vec<(usize, &str, &str)> = vec![];
+    for (index, line) in input.lines().enumerate() {
+        let line_number = index + 1;
+        let mut rest = line.split(['#', ';']).next().unwrap().trim();
+        while let Some(colon) = rest.find(':') {
+            let label = rest[..colon].trim();
+            if !is_identifier(label) {
+                return Err(AsmError::Malformed {
+                    line: line_number,
+                    text: line.to_string(),
+                });
+            }
+            if labels.insert(label, instructions.len()).is_some() {
+                return Err(AsmError::DuplicateLabel {
+                    line: line_number,
+                    label: label.to_string(),
+                });
+            }
+            rest = rest[colon + 1..].trim();
+        }
+        if rest.is_empty() {
+            continue;
+        }
+        match rest.split_whitespace().collect::<Vec<_>>().as_slice() {
+            [mnemonic, operand] => instructions.push((line_number, mnemonic, operand)),
+            _ => {
+                return Err(AsmError::Malformed {
+                    line: line_number,
+                    text: line.to_string(),
+                })
+            }
+        }
+    }
+
+    instructions
+        .iter()
+        .enumerate()
+        .map(|(address, (line, mnemonic, operand))| {
+            let param = match operand.parse::<isize>() {
+                Ok(x) => x,
+                Err(_) if is_identifier(operand) => match labels.get(operand) {
+                    Some(target) => *target as isize - address as isize,
+                    None => {
+                        return Err(AsmError::UnknownLabel {
+                            line: *line,
+                            label: operand.to_string(),
+                        })
+                    }
+                },
+                Err(_) => {
+                    return Err(AsmError::Malformed {
+                        line: *line,
+                        text: operand.to_string(),
+                    })
+                }
+            };
+            Operation::from_mnemonic(mnemonic, param).ok_or_else(|| AsmError::UnknownMnemonic {
+                line: *line,
+                mnemonic: mnemonic.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// the plain `nop +0` format read by `day8::generator`
+pub fn emit(program: &[Operation]) -> String {
+    program.iter().map(|x| format!("{}\n", x)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{generator, solve_part1, solve_part2};
+    use super::*;
+
+    const SOURCE: &str = "# the example from the puzzle
+        nop +0
+top:    acc +1
+        jmp skip        ; over the loop body
+body:   acc +3
+        jmp top
+        acc -99
+skip:   acc +1
+        jmp body
+        acc +6
+";
+
+    #[test]
+    fn it_assembles() {
+        assert_eq!(
+            "nop +0
+acc +1
+jmp +4
+acc +3
+jmp -3
+acc -99
+acc +1
+jmp -4
+acc +6
+",
+            emit(&assemble(SOURCE).unwrap())
+        );
+    }
+
+    #[test]
+    fn it_round_trips_through_the_generator() {
+        let program = assemble(SOURCE).unwrap();
+        assert_eq!(program, generator(&emit(&program)));
+        assert_eq!(5, solve_part1(&program));
+        assert_eq!(8, solve_part2(&program));
+    }
+
+    #[test]
+    fn it_resolves_labels_on_their_own_line_and_at_the_end() {
+        let source = "start:
+loop:
+    jz end
+    acc -1
+    jmp loop
+end:";
+        assert_eq!(
+            vec![Operation::Jz(3), Operation::Acc(-1), Operation::Jmp(-2)],
+            assemble(source).unwrap()
+        );
+    }
+
+    #[test]
+    fn it_reports_errors() {
+        assert_eq!(
+            Err(AsmError::UnknownLabel {
+                line: 2,
+                label: "nowhere".to_string()
+            }),
+            assemble("nop +0\njmp nowhere")
+        );
+        assert_eq!(
+            Err(AsmError::DuplicateLabel {
+                line: 2,
+                label: "a".to_string()
+            }),
+            assemble("a: nop +0\na: nop +0")
+        );
+        assert_eq!(
+            Err(AsmError::UnknownMnemonic {
+                line: 1,
+                mnemonic: "hcf".to_string()
+            }),
+            assemble("hcf +0")
+        );
+        assert_eq!(
+            Err(AsmError::Malformed {
+                line: 1,
+                text: "acc".to_string()
+            }),
+            assemble("acc")
+        );
+        assert_eq!(
+            Err(AsmError::Malformed {
+                line: 1,
+                text: "1x".to_string()
+            }),
+            assemble("acc 1x")
+        );
+    }
+}
@@ -0,0 +1,235 @@
+use super::Operation;
+use itertools::Itertools;
+use std::collections::{BTreeSet, HashSet, VecDeque};
+use std::fmt::Write;
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash, Ord, PartialOrd)]
+pub enum Target {
+    /// index into `ControlFlowGraph::blocks`
+    Block(usize),
+    /// one past the last instruction
+    Halt,
+    OutOfBounds(isize),
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct BasicBlock {
+    pub start: usize,
+    /// exclusive
+    pub end: usize,
+    pub successors: Vec<Target>,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct ControlFlowGraph {
+    pub blocks: Vec<BasicBlock>,
+}
+
+impl ControlFlowGraph {
+    pub fn new(input: &[Operation]) -> Self {
+        let in_program = |x: isize| (0..input.len() as isize).contains(&x);
+        // a block starts at the entry, at every jump target and right after every jump
+        let mut leaders: BTreeSet<usize> = BTreeSet::new();
+        leaders.insert(0);
+        for (index, op) in input.iter().enumerate() {
+            let successors = op.successors(index);
+            if successors != vec![index as isize + 1] {
+                leaders.extend(
+                    successors
+                        .into_iter()
+                        .filter(|x| in_program(*x))
+                        .map(|x| x as usize),
+                );
+                leaders.insert(index + 1);
+            }
+        }
+        let starts = leaders
+            .into_iter()
+            .filter(|x| *x < input.len())
+            .collect_vec();
+        let block_at = |x: isize| {
+            if x == input.len() as isize {
+                Target::Halt
+            } else if in_program(x) {
+                Target::Block(starts.binary_search(&(x as usize)).unwrap())
+            } else {
+                Target::OutOfBounds(x)
+            }
+        };
+        let blocks = starts
+            .iter()
+            .enumerate()
+            .map(|(block, start)| {
+                let end = starts.get(block + 1).cloned().unwrap_or(input.len());
+                BasicBlock {
+                    start: *start,
+                    end,
+                    successors: input[end - 1]
+                        .successors(end - 1)
+                        .into_iter()
+                        .map(block_at)
+                        .unique()
+                        .collect(),
+                }
+            })
+            .collect();
+        ControlFlowGraph { blocks }
+    }
+
+    fn block_successors(&self, block: usize) -> Vec<usize> {
+        self.blocks[block]
+            .successors
+            .iter()
+            .filter_map(|x| match x {
+                Target::Block(x) => Some(*x),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// strongly connected components which can actually go round, as sorted block indexes
+    pub fn loops(&self) -> Vec<Vec<usize>> {
+        let nodes = (0..self.blocks.len()).collect_vec();
+        pathfinding::directed::strongly_connected_components::strongly_connected_components(
+            &nodes,
+            |x| self.block_successors(*x),
+        )
+        .into_iter()
+        .filter(|x| x.len() > 1 || self.block_successors(x[0]).contains(&x[0]))
+        .map(|x| x.into_iter().sorted().collect_vec())
+        .sorted()
+        .collect()
+    }
+
+    /// blocks which can't be reached from the entry
+    pub fn unreachable(&self) -> Vec<usize> {
+        let mut seen: HashSet<usize> = HashSet::new();
+        let mut queue: VecDeque<usize> = VecDeque::new();
+        if !self.blocks.is_empty() {
+            seen.insert(0);
+            queue.push_back(0);
+        }
+        while let Some(next) = queue.pop_front() {
+            for x in self.block_successors(next) {
+                if seen.insert(x) {
+                    queue.push_back(x);
+                }
+            }
+        }
+        (0..self.blocks.len())
+            .filter(|x| !seen.contains(x))
+            .collect()
+    }
+
+    /// graphviz digraph with loops in red and unreachable blocks in grey
+    pub fn to_dot(&self, input: &[Operation]) -> String {
+        let looping = self.loops().into_iter().flatten().collect::<HashSet<_>>();
+        let unreachable = self.unreachable().into_iter().collect::<HashSet<_>>();
+        let mut out = String::from("digraph program {\n    node [shape=box];\n");
+        for (index, block) in self.blocks.iter().enumerate() {
+            let label: String = (block.start..block.end)
+                .map(|x| format!("{}: {}\\l", x, input[x]))
+                .collect();
+            let style = if unreachable.contains(&index) {
+                ", style=filled, fillcolor=grey"
+            } else if looping.contains(&index) {
+                ", color=red"
+            } else {
+                ""
+            };
+            writeln!(out, "    b{} [label=\"{}\"{}];", index, label, style).unwrap();
+        }
+        let mut out_of_bounds = BTreeSet::new();
+        for (index, block) in self.blocks.iter().enumerate() {
+            for target in &block.successors {
+                let node = match target {
+                    Target::Block(x) => format!("b{}", x),
+                    Target::Halt => "halt".to_string(),
+                    Target::OutOfBounds(x) => {
+                        out_of_bounds.insert(*x);
+                        format!("oob{}", x.to_string().replace('-', "m"))
+                    }
+                };
+                writeln!(out, "    b{} -> {};", index, node).unwrap();
+            }
+        }
+        if self
+            .blocks
+            .iter()
+            .any(|x| x.successors.contains(&Target::Halt))
+        {
+            out.push_str("    halt [shape=doublecircle];\n");
+        }
+        for x in out_of_bounds {
+            writeln!(
+                out,
+                "    oob{} [label=\"{} (out of bounds)\", shape=octagon];",
+                x.to_string().replace('-', "m"),
+                x
+            )
+            .unwrap();
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::generator;
+    use super::*;
+
+    const PROVIDED: &str = "nop +0
+acc +1
+jmp +4
+acc +3
+jmp -3
+acc -99
+acc +1
+jmp -4
+acc +6";
+
+    #[test]
+    fn it_finds_blocks() {
+        let cfg = ControlFlowGraph::new(&generator(PROVIDED));
+        assert_eq!(
+            vec![(0, 1), (1, 3), (3, 5), (5, 6), (6, 8), (8, 9)],
+            cfg.blocks.iter().map(|x| (x.start, x.end)).collect_vec()
+        );
+        assert_eq!(vec![Target::Block(4)], cfg.blocks[1].successors);
+        assert_eq!(vec![Target::Block(4)], cfg.blocks[3].successors);
+        assert_eq!(vec![Target::Halt], cfg.blocks[5].successors);
+    }
+
+    #[test]
+    fn it_finds_loops_and_unreachable_code() {
+        let cfg = ControlFlowGraph::new(&generator(PROVIDED));
+        assert_eq!(vec![vec![1, 2, 4]], cfg.loops());
+        assert_eq!(vec![3, 5], cfg.unreachable());
+
+        let cfg = ControlFlowGraph::new(&generator("acc +1\njmp +0\njmp -5"));
+        assert_eq!(vec![vec![1]], cfg.loops());
+        assert_eq!(vec![2], cfg.unreachable());
+        assert_eq!(vec![Target::OutOfBounds(-3)], cfg.blocks[2].successors);
+    }
+
+    #[test]
+    fn it_exports_dot() {
+        let provided = generator("acc +1\njz +2\njmp -2\njmp +5");
+        assert_eq!(
+            "digraph program {
+    node [shape=box];
+    b0 [label=\"0: acc +1\\l1: jz +2\\l\", color=red];
+    b1 [label=\"2: jmp -2\\l\", color=red];
+    b2 [label=\"3: jmp +5\\l\"];
+    b0 -> b1;
+    b0 -> b2;
+    b1 -> b0;
+    b2 -> oob8;
+    oob8 [label=\"8 (out of bounds)\", shape=octagon];
+}
+",
+            ControlFlowGraph::new(&provided).to_dot(&provided)
+        );
+    }
+}
@@ -0,0 +1,311 @@
+use super::{parse_program, Machine, Operation, StepOutcome};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Write as _;
+use std::io::{self, BufRead, Write};
+
+/// `continue` gives up after this many instructions so a program which never repeats can't hang the debugger
+const CONTINUE_LIMIT: usize = 1_000_000;
+
+const HELP: &str =
+    "break <address|opcode>  stop before an address or any instruction with that opcode
+delete <address|opcode> remove a breakpoint
+breakpoints             list breakpoints
+step [n]                execute n instructions (default 1)
+continue                run until a breakpoint, a repeated state or the program stops
+print [acc|rip]         show registers
+list                    show the program
+patch <address> <op>    replace an instruction, e.g. patch 7 nop -4
+flip <address>          swap a nop for a jmp or the other way round
+reset                   restart the (patched) program
+quit                    exit";
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum Breakpoint {
+    Address(usize),
+    Opcode(String),
+}
+
+impl Breakpoint {
+    fn parse(input: &str) -> Breakpoint {
+        match input.parse::<usize>() {
+            Ok(x) => Breakpoint::Address(x),
+            Err(_) => Breakpoint::Opcode(input.to_string()),
+        }
+    }
+
+    fn matches(&self, machine: &Machine) -> bool {
+        match self {
+            Breakpoint::Address(x) => machine.rip == *x as isize,
+            Breakpoint::Opcode(x) => match machine.program.get(machine.rip as usize) {
+                Some(op) => op.mnemonic() == x,
+                None => false,
+            },
+        }
+    }
+}
+
+type State = (isize, isize, BTreeMap<isize, isize>);
+
+pub struct Debugger {
+    pub machine: Machine,
+    pub breakpoints: Vec<Breakpoint>,
+    /// every state seen since the last patch or reset
+    seen: HashSet<State>,
+}
+
+impl Debugger {
+    pub fn new(program: Vec<Operation>) -> Self {
+        Debugger {
+            machine: Machine::new(program),
+            breakpoints: vec![],
+            seen: HashSet::new(),
+        }
+    }
+
+    fn state(&self) -> State {
+        (
+            self.machine.rip,
+            self.machine.acc,
+            self.machine.registers.clone().into_iter().collect(),
+        )
+    }
+
+    fn location(&self) -> String {
+        match self.machine.status() {
+            StepOutcome::Halted => format!("halted acc={}", self.machine.acc),
+            StepOutcome::OutOfBounds => format!(
+                "out of bounds rip={} acc={}",
+                self.machine.rip, self.machine.acc
+            ),
+            _ => format!(
+                "rip={} acc={}: {}",
+                self.machine.rip, self.machine.acc, self.machine.program[self.machine.rip as usize]
+            ),
+        }
+    }
+
+    /// execute one instruction, returns a message if the debugger should stop
+    fn advance(&mut self) -> Option<String> {
+        match self.machine.status() {
+            StepOutcome::Halted | StepOutcome::OutOfBounds => return Some(self.location()),
+            // the debugger keeps its own limit on `continue`
+            StepOutcome::Continued | StepOutcome::Looped | StepOutcome::StepLimit => {}
+        }
+        self.seen.insert(self.state());
+        self.machine.execute();
+        if self.seen.contains(&self.state()) {
+            Some(format!("state repeats, {}", self.location()))
+        } else {
+            None
+        }
+    }
+
+    fn step(&mut self, count: usize) -> String {
+        for _ in 0..count {
+            if let Some(x) = self.advance() {
+                return x;
+            }
+        }
+        self.location()
+    }
+
+    fn resume(&mut self) -> String {
+        for steps in 0..CONTINUE_LIMIT {
+            if steps > 0 {
+                if let Some(x) = self.breakpoints.iter().find(|x| x.matches(&self.machine)) {
+                    return format!("breakpoint {:?}, {}", x, self.location());
+                }
+            }
+            if let Some(x) = self.advance() {
+                return x;
+            }
+        }
+        format!(
+            "gave up after {} steps, {}",
+            CONTINUE_LIMIT,
+            self.location()
+        )
+    }
+
+    fn list(&self) -> String {
+        let mut out = String::new();
+        for (index, op) in self.machine.program.iter().enumerate() {
+            let marker = if index as isize == self.machine.rip {
+                ">"
+            } else {
+                " "
+            };
+            writeln!(out, "{} {:>4} {}", marker, index, op).unwrap();
+        }
+        out.trim_end().to_string()
+    }
+
+    fn patch(&mut self, address: &str, op: Option<Operation>) -> String {
+        let address = match address.parse::<usize>() {
+            Ok(x) if x < self.machine.program.len() => x,
+            _ => return format!("no instruction at {}", address),
+        };
+        let old = self.machine.program[address];
+        self.machine.program[address] = op.unwrap_or_else(|| old.flip());
+        self.seen.clear();
+        format!("{}: {} -> {}", address, old, self.machine.program[address])
+    }
+
+    /// run a single command and describe the result
+    pub fn command(&mut self, line: &str) -> String {
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        match words.as_slice() {
+            ["break", x] | ["b", x] => {
+                self.breakpoints.push(Breakpoint::parse(x));
+                format!("breakpoint {:?}", Breakpoint::parse(x))
+            }
+            ["delete", x] | ["d", x] => {
+                let before = self.breakpoints.len();
+                self.breakpoints.retain(|y| *y != Breakpoint::parse(x));
+                format!("deleted {}", before - self.breakpoints.len())
+            }
+            ["breakpoints"] => format!("{:?}", self.breakpoints),
+            ["step"] | ["s"] => self.step(1),
+            ["step", x] | ["s", x] => match x.parse() {
+                Ok(x) => self.step(x),
+                Err(_) => format!("not a number: {}", x),
+            },
+            ["continue"] | ["c"] => self.resume(),
+            ["print"] | ["p"] => format!("rip={} acc={}", self.machine.rip, self.machine.acc),
+            ["print", "acc"] | ["p", "acc"] => format!("{}", self.machine.acc),
+            ["print", "rip"] | ["p", "rip"] => format!("{}", self.machine.rip),
+            ["list"] | ["l"] => self.list(),
+            ["patch", address, op @ ..] if !op.is_empty() => match parse_program(&op.join(" ")) {
+                Ok(x) if x.len() == 1 => self.patch(address, Some(x[0])),
+                Ok(_) => format!("expected one instruction: {}", op.join(" ")),
+                Err(e) => e.to_string(),
+            },
+            ["flip", address] => self.patch(address, None),
+            ["reset"] => {
+                self.machine = Machine::new(self.machine.program.clone());
+                self.seen.clear();
+                self.location()
+            }
+            ["help"] | ["h"] => HELP.to_string(),
+            _ => format!("unknown command: {}", line.trim()),
+        }
+    }
+}
+
+/// read commands from `input` until it ends or says `quit`, writing one response per command
+pub fn repl<R: BufRead, W: Write>(
+    program: Vec<Operation>,
+    input: R,
+    mut output: W,
+) -> io::Result<()> {
+    let mut debugger = Debugger::new(program);
+    writeln!(output, "{}", debugger.location())?;
+    for line in input.lines() {
+        let line = line?;
+        match line.trim() {
+            "" => continue,
+            "quit" | "q" => break,
+            _ => writeln!(output, "{}", debugger.command(&line))?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::generator;
+    use super::*;
+
+    const PROVIDED: &str = "nop +0
+acc +1
+jmp +4
+acc +3
+jmp -3
+acc -99
+acc +1
+jmp -4
+acc +6";
+
+    fn session(script: &str) -> String {
+        let mut output = vec![];
+        repl(generator(PROVIDED), script.as_bytes(), &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn it_steps_and_prints() {
+        assert_eq!(
+            "rip=0 acc=0: nop +0
+rip=1 acc=0: acc +1
+rip=6 acc=1: acc +1
+1
+6
+rip=6 acc=1
+",
+            session("step\nstep 2\n\nprint acc\nprint rip\nprint\nquit\nstep")
+        );
+    }
+
+    #[test]
+    fn it_stops_at_breakpoints() {
+        assert_eq!(
+            "rip=0 acc=0: nop +0
+breakpoint Opcode(\"jmp\")
+breakpoint Address(3)
+breakpoint Opcode(\"jmp\"), rip=2 acc=1: jmp +4
+breakpoint Opcode(\"jmp\"), rip=7 acc=2: jmp -4
+breakpoint Address(3), rip=3 acc=2: acc +3
+deleted 1
+[Address(3)]
+",
+            session("break jmp\nbreak 3\nc\nc\nc\ndelete jmp\nbreakpoints")
+        );
+    }
+
+    #[test]
+    fn it_detects_repeated_state() {
+        assert_eq!(
+            "rip=0 acc=0: nop +0
+3: acc +3 -> acc -2
+state repeats, rip=1 acc=0: acc +1
+state repeats, rip=2 acc=1: jmp +4
+",
+            session("patch 3 acc -2\ncontinue\nstep")
+        );
+    }
+
+    #[test]
+    fn it_patches_and_halts() {
+        assert_eq!(
+            "rip=0 acc=0: nop +0
+7: jmp -4 -> nop -4
+halted acc=8
+rip=0 acc=0: nop +0
+",
+            session("flip 7\ncontinue\nreset")
+        );
+    }
+
+    #[test]
+    fn it_lists() {
+        let mut debugger = Debugger::new(generator("nop +0\nacc +1\njmp -2"));
+        debugger.command("step");
+        assert_eq!(
+            "     0 nop +0\n>    1 acc +1\n     2 jmp -2",
+            debugger.command("list")
+        );
+    }
+
+    #[test]
+    fn it_reports_bad_commands() {
+        assert_eq!(
+            "rip=0 acc=0: nop +0
+unknown command: jump 3
+no instruction at 99
+line 1: unknown mnemonic hcf
+not a number: x
+",
+            session("jump 3\nflip 99\npatch 1 hcf +0\nstep x")
+        );
+    }
+}
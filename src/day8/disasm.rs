@@ -0,0 +1,84 @@
+use super::Operation;
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+/// absolute address of a jump, if the instruction jumps
+pub fn jump_target(op: &Operation, index: usize) -> Option<isize> {
+    match op {
+        Operation::Jmp(x) | Operation::Jz(x) => Some((index as isize).saturating_add(*x)),
+        _ => None,
+    }
+}
+
+/// one line per instruction with a label on every jump target and the absolute address next to each jump
+pub fn disassemble(input: &[Operation]) -> String {
+    let in_range = |x: isize| (0..=input.len() as isize).contains(&x);
+    let labels = input
+        .iter()
+        .enumerate()
+        .filter_map(|(index, op)| jump_target(op, index))
+        .filter(|x| in_range(*x))
+        .map(|x| x as usize)
+        .collect::<BTreeSet<_>>();
+    let mut out = String::new();
+    for (index, op) in input.iter().enumerate() {
+        let label = if labels.contains(&index) {
+            format!("L{}:", index)
+        } else {
+            String::new()
+        };
+        let comment = match jump_target(op, index) {
+            Some(x) if x == input.len() as isize => format!("  ; -> L{} (end)", x),
+            Some(x) if in_range(x) => format!("  ; -> L{}", x),
+            Some(x) => format!("  ; -> {} (out of bounds)", x),
+            None => String::new(),
+        };
+        let line = format!(
+            "{:>4}  {:<6} {:<8}{}",
+            index,
+            label,
+            op.to_string(),
+            comment
+        );
+        writeln!(out, "{}", line.trim_end()).unwrap();
+    }
+    if labels.contains(&input.len()) {
+        writeln!(out, "{:>4}  L{}:", input.len(), input.len()).unwrap();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::generator;
+    use super::*;
+
+    #[test]
+    fn it_disassembles() {
+        let provided = "nop +0
+acc +1
+jmp +4
+acc +3
+jmp -3
+acc -99
+acc +1
+jmp -4
+jz +2
+jmp +50";
+        assert_eq!(
+            "   0         nop +0
+   1  L1:    acc +1
+   2         jmp +4    ; -> L6
+   3  L3:    acc +3
+   4         jmp -3    ; -> L1
+   5         acc -99
+   6  L6:    acc +1
+   7         jmp -4    ; -> L3
+   8         jz +2     ; -> L10 (end)
+   9         jmp +50   ; -> 59 (out of bounds)
+  10  L10:
+",
+            disassemble(&generator(provided))
+        );
+    }
+}
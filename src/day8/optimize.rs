@@ -0,0 +1,242 @@
+use super::cfg::ControlFlowGraph;
+use super::disasm::jump_target;
+use super::Operation;
+use std::collections::HashSet;
+
+/// the same instruction pointing somewhere else
+fn retarget(op: Operation, param: isize) -> Operation {
+    Operation::from_mnemonic(op.mnemonic(), param).unwrap()
+}
+
+/// rebuild the program without the instructions replaced by `None` and fix up every relative jump
+///
+/// anything jumping to a removed instruction lands on whatever followed it, so only instructions
+/// which fall through can be removed that way
+fn compact(input: &[Operation], ops: Vec<Option<Operation>>) -> Vec<Operation> {
+    let len = input.len();
+    let new_len = ops.iter().filter(|x| x.is_some()).count();
+    let mut address = vec![new_len; len + 1];
+    let mut next = new_len;
+    for index in (0..len).rev() {
+        if ops[index].is_some() {
+            next -= 1;
+        }
+        address[index] = next;
+    }
+    let relocate = |target: isize| {
+        if target < 0 {
+            target
+        } else if target > len as isize {
+            new_len as isize + (target - len as isize)
+        } else {
+            address[target as usize] as isize
+        }
+    };
+    ops.into_iter()
+        .enumerate()
+        .filter_map(|(index, op)| {
+            op.map(|op| match jump_target(&op, index) {
+                Some(target) => {
+                    retarget(op, relocate(target).saturating_sub(address[index] as isize))
+                }
+                None => op,
+            })
+        })
+        .collect()
+}
+
+/// jumps which land on a `jmp` go straight to where that one ends up, stopping if the chain goes round
+fn thread_jumps(input: &[Operation]) -> Vec<Operation> {
+    let in_program = |x: isize| (0..input.len() as isize).contains(&x);
+    input
+        .iter()
+        .enumerate()
+        .map(|(index, op)| {
+            // a `jz +1` can only go one way, threading it would turn it into a real branch
+            let mut target = match jump_target(op, index) {
+                Some(_) if *op == Operation::Jz(1) => return *op,
+                Some(x) => x,
+                None => return *op,
+            };
+            let mut seen = HashSet::new();
+            while in_program(target) && seen.insert(target) {
+                match input[target as usize] {
+                    Operation::Jmp(x) => target = target.saturating_add(x),
+                    _ => break,
+                }
+            }
+            retarget(*op, target.saturating_sub(index as isize))
+        })
+        .collect()
+}
+
+fn remove_unreachable(input: &[Operation]) -> Vec<Operation> {
+    let cfg = ControlFlowGraph::new(input);
+    let dead = cfg
+        .unreachable()
+        .into_iter()
+        .flat_map(|x| cfg.blocks[x].start..cfg.blocks[x].end)
+        .collect::<HashSet<_>>();
+    let ops = input
+        .iter()
+        .enumerate()
+        .map(|(index, op)| {
+            if dead.contains(&index) {
+                None
+            } else {
+                Some(*op)
+            }
+        })
+        .collect();
+    compact(input, ops)
+}
+
+/// merge runs of `acc` unless something jumps into the middle of them
+fn fold_accs(input: &[Operation]) -> Vec<Operation> {
+    let targets = input
+        .iter()
+        .enumerate()
+        .filter_map(|(index, op)| jump_target(op, index))
+        .collect::<HashSet<_>>();
+    let mut ops: Vec<Option<Operation>> = vec![];
+    let mut run: Option<usize> = None;
+    for (index, op) in input.iter().enumerate() {
+        match (op, run) {
+            (Operation::Acc(x), Some(start)) if !targets.contains(&(index as isize)) => {
+                ops[start] = Some(Operation::Acc(ops[start].unwrap().param().wrapping_add(*x)));
+                ops.push(None);
+            }
+            (Operation::Acc(_), _) => {
+                run = Some(index);
+                ops.push(Some(*op));
+            }
+            _ => {
+                run = None;
+                ops.push(Some(*op));
+            }
+        }
+    }
+    compact(input, ops)
+}
+
+/// drop everything which always carries on with the next instruction and leaves acc alone
+fn remove_nops(input: &[Operation]) -> Vec<Operation> {
+    let ops = input
+        .iter()
+        .map(|op| match op {
+            Operation::Nop(_) | Operation::Acc(0) | Operation::Jmp(1) | Operation::Jz(1) => None,
+            _ => Some(*op),
+        })
+        .collect();
+    compact(input, ops)
+}
+
+/// a shorter program which ends the same way with the same acc, whether it halts, jumps out of
+/// bounds or loops, it may take fewer steps so one which hit the step limit can get further
+///
+/// removed instructions are never the first one to repeat since only jump targets can be, and
+/// jumps into removed code are redirected to the instruction which would have run next
+pub fn optimize(input: &[Operation]) -> Vec<Operation> {
+    let mut program = input.to_vec();
+    loop {
+        let next = remove_nops(&fold_accs(&remove_unreachable(&thread_jumps(&program))));
+        if next == program {
+            return program;
+        }
+        program = next;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{execute_from, generator, Termination, OPCODES};
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::seq::SliceRandom;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn it_optimizes_mini() {
+        let provided = generator(
+            "nop +0
+acc +1
+jmp +4
+acc +3
+jmp -3
+acc -99
+acc +1
+jmp -4
+acc +6",
+        );
+        let optimized = optimize(&provided);
+        assert_eq!(
+            generator("acc +1\njmp +3\nacc +3\njmp -3\nacc +1\njmp -3"),
+            optimized
+        );
+        assert_eq!(5, execute_from(&optimized, 0).acc);
+    }
+
+    #[test]
+    fn it_optimizes_real_input() {
+        let provided = generator(include_str!("../../input/2020/day8.txt"));
+        let optimized = optimize(&provided);
+        assert!(optimized.len() < provided.len());
+        assert_eq!(
+            execute_from(&provided, 0).acc,
+            execute_from(&optimized, 0).acc
+        );
+    }
+
+    #[test]
+    fn it_folds_and_threads() {
+        let provided = generator(
+            "acc +1
+acc +2
+jmp +2
+acc +99
+jmp +2
+acc -1
+acc +4",
+        );
+        assert_eq!(vec![Operation::Acc(7)], optimize(&provided));
+    }
+
+    #[test]
+    fn it_keeps_loops_and_out_of_bounds_jumps() {
+        assert_eq!(generator("jmp +0"), optimize(&generator("nop +0\njmp +0")));
+        assert_eq!(generator("jmp +5"), optimize(&generator("nop +0\njmp +5")));
+        assert_eq!(generator("jmp -2"), optimize(&generator("nop +0\njmp -3")));
+        assert_eq!(generator("jmp +0"), optimize(&generator("jmp +1\njmp -1")));
+    }
+
+    #[test]
+    fn it_matches_the_original_on_random_programs() {
+        for seed in 0..5000 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let len = rng.gen_range(0..16) as isize;
+            let program = (0..len)
+                .map(|_| {
+                    let (mnemonic, build) = OPCODES.choose(&mut rng).unwrap();
+                    match *mnemonic {
+                        "jmp" | "jz" | "nop" => build(rng.gen_range(-len - 1..=len + 1)),
+                        _ => build(rng.gen_range(-3..=3)),
+                    }
+                })
+                .collect::<Vec<_>>();
+            let optimized = optimize(&program);
+            let (before, after) = (execute_from(&program, 0), execute_from(&optimized, 0));
+            // the optimized program takes fewer steps, so it can get further before the limit
+            if before.termination == Termination::StepLimit {
+                continue;
+            }
+            assert!(optimized.len() <= program.len());
+            assert_eq!(
+                (before.termination, before.acc),
+                (after.termination, after.acc),
+                "{:?} optimized to {:?}",
+                program,
+                optimized
+            );
+        }
+    }
+}
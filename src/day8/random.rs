@@ -0,0 +1,203 @@
+use super::Operation;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Shape {
+    /// every instruction is drawn independently, so it may do anything
+    Random,
+    /// runs off the end onto the instruction after the last one
+    Halting,
+    /// repeats an instruction, and flipping the last instruction back into a `nop` makes it halt
+    Looping,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProgramConfig {
+    pub len: usize,
+    /// furthest a `jmp` or `nop` operand points, except where the shape needs a longer jump
+    pub max_jump: usize,
+    /// chance of an instruction being a `jmp` or `nop` rather than an `acc`
+    pub jump_ratio: f64,
+    pub max_acc: isize,
+    pub shape: Shape,
+    pub seed: u64,
+}
+
+impl Default for ProgramConfig {
+    fn default() -> Self {
+        ProgramConfig {
+            len: 600,
+            max_jump: 50,
+            jump_ratio: 0.5,
+            max_acc: 50,
+            shape: Shape::Random,
+            seed: 0,
+        }
+    }
+}
+
+fn acc(rng: &mut StdRng, config: &ProgramConfig) -> Operation {
+    Operation::Acc(rng.gen_range(-config.max_acc..=config.max_acc))
+}
+
+fn offset(rng: &mut StdRng, config: &ProgramConfig) -> isize {
+    rng.gen_range(-(config.max_jump as isize)..=config.max_jump as isize)
+}
+
+fn anything(rng: &mut StdRng, config: &ProgramConfig) -> Operation {
+    if rng.gen_bool(config.jump_ratio) {
+        let offset = offset(rng, config);
+        if rng.gen_bool(0.5) {
+            Operation::Jmp(offset)
+        } else {
+            Operation::Nop(offset)
+        }
+    } else {
+        acc(rng, config)
+    }
+}
+
+/// a program using only `nop`, `acc` and `jmp`, the same seed always gives the same program
+///
+/// the halting and looping shapes pick a random route from the first to the last instruction and
+/// only fill in the rest at random
+pub fn random_program(config: &ProgramConfig) -> Vec<Operation> {
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let mut program = (0..config.len)
+        .map(|_| anything(&mut rng, config))
+        .collect::<Vec<_>>();
+    if config.shape == Shape::Random {
+        return program;
+    }
+    assert!(config.len > 0, "an empty program can only halt");
+
+    let last = config.len - 1;
+    let mut route = (1..last).collect::<Vec<_>>();
+    route.shuffle(&mut rng);
+    route.truncate(rng.gen_range(0..=route.len()));
+    route.insert(0, 0);
+    if last > 0 {
+        route.push(last);
+    }
+    for (from, to) in route.iter().zip(route.iter().skip(1)) {
+        program[*from] = if *to == from + 1 && !rng.gen_bool(config.jump_ratio) {
+            acc(&mut rng, config)
+        } else if *to == from + 1 {
+            Operation::Nop(offset(&mut rng, config))
+        } else {
+            Operation::Jmp(*to as isize - *from as isize)
+        };
+    }
+    program[last] = match config.shape {
+        Shape::Looping => Operation::Jmp(*route.choose(&mut rng).unwrap() as isize - last as isize),
+        _ if rng.gen_bool(config.jump_ratio) => Operation::Nop(offset(&mut rng, config)),
+        _ => acc(&mut rng, config),
+    };
+    program
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::repair::{repair, search, Patch, PatchSearch};
+    use super::super::{execute_from, Termination};
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn it_is_deterministic() {
+        let config = ProgramConfig::default();
+        assert_eq!(random_program(&config), random_program(&config));
+        assert_ne!(
+            random_program(&config),
+            random_program(&ProgramConfig { seed: 1, ..config })
+        );
+    }
+
+    #[test]
+    fn it_respects_the_jump_distribution() {
+        let config = ProgramConfig {
+            max_jump: 3,
+            jump_ratio: 0.0,
+            ..ProgramConfig::default()
+        };
+        assert!(random_program(&config).iter().all(|x| x.is_acc()));
+        let config = ProgramConfig {
+            jump_ratio: 1.0,
+            ..config
+        };
+        assert!(random_program(&config)
+            .iter()
+            .all(|x| !x.is_acc() && x.param().abs() <= 3));
+    }
+
+    fn config() -> impl Strategy<Value = ProgramConfig> {
+        (
+            1usize..200,
+            1usize..100,
+            0.0..=1.0,
+            prop_oneof![
+                Just(Shape::Random),
+                Just(Shape::Halting),
+                Just(Shape::Looping)
+            ],
+            any::<u64>(),
+        )
+            .prop_map(|(len, max_jump, jump_ratio, shape, seed)| ProgramConfig {
+                len,
+                max_jump,
+                jump_ratio,
+                max_acc: 50,
+                shape,
+                seed,
+            })
+    }
+
+    proptest! {
+        #[test]
+        fn it_executes_consistently(config in config()) {
+            let program = random_program(&config);
+            prop_assert_eq!(config.len, program.len());
+            let report = execute_from(&program, 0);
+            prop_assert!(report.visited.iter().all(|x| *x < program.len()));
+            // nothing runs twice, so acc is just the sum of the visited acc operands
+            let sum: isize = report
+                .visited
+                .iter()
+                .filter(|x| program[**x].is_acc())
+                .map(|x| program[*x].param())
+                .sum();
+            prop_assert_eq!(sum, report.acc);
+            match config.shape {
+                Shape::Halting => prop_assert_eq!(Termination::Halted, report.termination),
+                Shape::Looping => prop_assert_eq!(Termination::Looped, report.termination),
+                Shape::Random => {}
+            }
+        }
+
+        #[test]
+        fn it_repairs_with_one_flip(config in config()) {
+            let program = random_program(&config);
+            let fixed = repair(&program);
+            if config.shape == Shape::Looping {
+                prop_assert!(fixed.is_some());
+            }
+            if let Some(fixed) = fixed {
+                let mut patched = program.clone();
+                patched[fixed.index] = patched[fixed.index].flip();
+                let differences = program.iter().zip(&patched).filter(|(a, b)| a != b).count();
+                prop_assert_eq!(1, differences);
+                let report = execute_from(&patched, 0);
+                prop_assert_eq!(Termination::Halted, report.termination);
+                prop_assert_eq!(fixed.acc, report.acc);
+                // a program which already halts needs no patches at all
+                if execute_from(&program, 0).termination != Termination::Halted {
+                    let options = PatchSearch { budget: 1, allow_deletions: false };
+                    let solutions = search(&program, &options).unwrap();
+                    prop_assert!(solutions.iter().any(|x| x.patches == vec![Patch::Flip(fixed.index)]));
+                }
+            }
+        }
+    }
+}
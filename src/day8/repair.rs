@@ -0,0 +1,373 @@
+use super::{execute_from, Machine, Operation, StepOutcome, Termination};
+use std::collections::{BTreeSet, HashSet, VecDeque};
+use std::fmt;
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct Repair {
+    /// the instruction which was flipped
+    pub index: usize,
+    /// acc after the patched program halts
+    pub acc: isize,
+}
+
+/// for every address up to and including `input.len()`, whether following the program from there can halt
+fn reaches_end(input: &[Operation]) -> Vec<bool> {
+    let end = input.len();
+    let mut reverse: Vec<Vec<usize>> = vec![vec![]; end + 1];
+    for (index, op) in input.iter().enumerate() {
+        for target in op.successors(index) {
+            if (0..=end as isize).contains(&target) {
+                reverse[target as usize].push(index);
+            }
+        }
+    }
+    let mut reaches = vec![false; end + 1];
+    let mut queue = VecDeque::from(vec![end]);
+    reaches[end] = true;
+    while let Some(next) = queue.pop_front() {
+        for source in &reverse[next] {
+            if !reaches[*source] {
+                reaches[*source] = true;
+                queue.push_back(*source);
+            }
+        }
+    }
+    reaches
+}
+
+/// find the single nop/jmp flip which makes the program halt
+///
+/// without `jz` the original run only visits each instruction once before looping, and the first
+/// visited instruction whose flipped successor can reach the end is the fix, so this is linear
+///
+/// with `jz` a candidate can still fail to halt, so each one is run in full, the original run and
+/// every candidate can take up to `STEP_LIMIT` steps and a fix which needs longer isn't found
+pub fn repair(input: &[Operation]) -> Option<Repair> {
+    let reaches = reaches_end(input);
+    let mut machine = Machine::new(input.to_vec());
+    let mut tried = HashSet::new();
+    while machine.status() == StepOutcome::Continued {
+        let index = machine.rip as usize;
+        let flipped = input[index].flip();
+        if flipped != input[index]
+            && tried.insert(index)
+            && flipped.successors(index).iter().any(|target| {
+                (0..=input.len() as isize).contains(target) && reaches[*target as usize]
+            })
+        {
+            let mut patched = input.to_vec();
+            patched[index] = flipped;
+            let report = execute_from(&patched, 0);
+            if report.termination == Termination::Halted {
+                return Some(Repair {
+                    index,
+                    acc: report.acc,
+                });
+            }
+        }
+        machine.step();
+    }
+    None
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash, Ord, PartialOrd)]
+pub enum Patch {
+    Flip(usize),
+    /// remove the instruction, relative jumps over it land one instruction further
+    Delete(usize),
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct PatchSearch {
+    /// most patches a solution may use
+    pub budget: usize,
+    pub allow_deletions: bool,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Solution {
+    /// addresses refer to the unpatched program
+    pub patches: Vec<Patch>,
+    pub acc: isize,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum SearchError {
+    NoFixWithinBudget { budget: usize },
+}
+
+impl fmt::Display for SearchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SearchError::NoFixWithinBudget { budget } => {
+                write!(f, "no fix within {} patches", budget)
+            }
+        }
+    }
+}
+
+/// the patched program along with the original address of every remaining instruction
+fn apply(input: &[Operation], patches: &BTreeSet<Patch>) -> (Vec<Operation>, Vec<usize>) {
+    input
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| !patches.contains(&Patch::Delete(*index)))
+        .map(|(index, op)| {
+            if patches.contains(&Patch::Flip(index)) {
+                (op.flip(), index)
+            } else {
+                (*op, index)
+            }
+        })
+        .unzip()
+}
+
+fn explore(
+    input: &[Operation],
+    options: &PatchSearch,
+    patches: &mut BTreeSet<Patch>,
+    remaining: usize,
+    solutions: &mut BTreeSet<(Vec<Patch>, isize)>,
+) {
+    let (program, origin) = apply(input, patches);
+    let report = execute_from(&program, 0);
+    if remaining == 0 {
+        if report.termination == Termination::Halted {
+            solutions.insert((patches.iter().cloned().collect(), report.acc));
+        }
+        return;
+    }
+    // a flip which isn't executed can't change anything, so only look at the current path, but
+    // deleting any instruction moves everything after it and changes where jumps over it land
+    let unpatched = |x: &usize| !patches.contains(&Patch::Flip(*x));
+    let mut candidates = report
+        .visited
+        .iter()
+        .map(|x| origin[*x])
+        .filter(|x| unpatched(x) && input[*x].flip() != input[*x])
+        .map(Patch::Flip)
+        .collect::<Vec<_>>();
+    if options.allow_deletions {
+        candidates.extend(
+            origin
+                .iter()
+                .filter(|x| unpatched(x))
+                .map(|x| Patch::Delete(*x)),
+        );
+    }
+    for patch in candidates {
+        patches.insert(patch);
+        explore(input, options, patches, remaining - 1, solutions);
+        patches.remove(&patch);
+    }
+}
+
+/// every smallest set of patches within the budget which makes the program halt
+pub fn search(input: &[Operation], options: &PatchSearch) -> Result<Vec<Solution>, SearchError> {
+    for size in 0..=options.budget {
+        let mut solutions = BTreeSet::new();
+        explore(input, options, &mut BTreeSet::new(), size, &mut solutions);
+        if !solutions.is_empty() {
+            return Ok(solutions
+                .into_iter()
+                .map(|(patches, acc)| Solution { patches, acc })
+                .collect());
+        }
+    }
+    Err(SearchError::NoFixWithinBudget {
+        budget: options.budget,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::generator;
+    use super::*;
+
+    #[test]
+    fn it_repairs_mini() {
+        let provided = "nop +0
+acc +1
+jmp +4
+acc +3
+jmp -3
+acc -99
+acc +1
+jmp -4
+acc +6";
+        assert_eq!(
+            Some(Repair { index: 7, acc: 8 }),
+            repair(&generator(provided))
+        );
+    }
+
+    #[test]
+    fn it_repairs_real_input() {
+        let provided = generator(include_str!("../../input/2020/day8.txt"));
+        let fixed = repair(&provided).unwrap();
+        assert_eq!(1245, fixed.acc);
+        assert_ne!(provided[fixed.index], provided[fixed.index].flip());
+    }
+
+    #[test]
+    fn it_ignores_jumps_past_the_end() {
+        // flipping the nop jumps past the end, only flipping the last jmp halts properly
+        let provided = "nop +5
+acc +1
+jmp -1";
+        assert_eq!(
+            Some(Repair { index: 2, acc: 1 }),
+            repair(&generator(provided))
+        );
+    }
+
+    #[test]
+    fn it_repairs_after_a_jz_loop() {
+        // the countdown runs its loop three times before reaching the broken jmp
+        let provided = "acc +3
+acc -1
+jz +2
+jmp -2
+jmp +0";
+        assert_eq!(
+            Some(Repair { index: 4, acc: 0 }),
+            repair(&generator(provided))
+        );
+    }
+
+    #[test]
+    fn it_gives_up_on_long_loops() {
+        // the jz is only taken once acc wraps round, long after the step limit
+        assert_eq!(None, repair(&generator("acc +1\njz +2\njmp -2\njmp +0")));
+    }
+
+    #[test]
+    fn it_gives_up_without_a_fix() {
+        assert_eq!(None, repair(&generator("acc +1\njmp +0\njmp -2")));
+    }
+
+    const TWO_FLIPS: &str = "nop +0
+jmp +0
+jmp -2
+acc +1";
+
+    #[test]
+    fn it_searches_single_flips() {
+        let provided = generator(include_str!("../../input/2020/day8.txt"));
+        let options = PatchSearch {
+            budget: 1,
+            allow_deletions: false,
+        };
+        let fixed = repair(&provided).unwrap();
+        assert_eq!(
+            Ok(vec![Solution {
+                patches: vec![Patch::Flip(fixed.index)],
+                acc: fixed.acc
+            }]),
+            search(&provided, &options)
+        );
+    }
+
+    #[test]
+    fn it_accepts_halting_programs() {
+        let options = PatchSearch {
+            budget: 2,
+            allow_deletions: true,
+        };
+        assert_eq!(
+            Ok(vec![Solution {
+                patches: vec![],
+                acc: 1
+            }]),
+            search(&generator("acc +1\nnop +0"), &options)
+        );
+    }
+
+    #[test]
+    fn it_searches_multiple_flips() {
+        let mut options = PatchSearch {
+            budget: 1,
+            allow_deletions: false,
+        };
+        assert_eq!(
+            Err(SearchError::NoFixWithinBudget { budget: 1 }),
+            search(&generator(TWO_FLIPS), &options)
+        );
+        options.budget = 3;
+        assert_eq!(
+            Ok(vec![Solution {
+                patches: vec![Patch::Flip(1), Patch::Flip(2)],
+                acc: 1
+            }]),
+            search(&generator(TWO_FLIPS), &options)
+        );
+    }
+
+    #[test]
+    fn it_deletes_instructions_which_never_run() {
+        // removing the nop which is jumped over makes jmp +4 land exactly on the end
+        let provided = vec![
+            Operation::Jmp(4),
+            Operation::Acc(0),
+            Operation::Jmp(-4),
+            Operation::Nop(-3),
+            Operation::Jmp(-3),
+        ];
+        let options = PatchSearch {
+            budget: 1,
+            allow_deletions: true,
+        };
+        assert_eq!(
+            vec![
+                Patch::Flip(4),
+                Patch::Delete(1),
+                Patch::Delete(2),
+                Patch::Delete(3),
+                Patch::Delete(4)
+            ],
+            search(&provided, &options)
+                .unwrap()
+                .into_iter()
+                .flat_map(|x| x.patches)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn it_searches_deletions() {
+        let options = PatchSearch {
+            budget: 1,
+            allow_deletions: true,
+        };
+        assert_eq!(
+            Ok(vec![
+                Solution {
+                    patches: vec![Patch::Flip(1)],
+                    acc: 1
+                },
+                Solution {
+                    patches: vec![Patch::Delete(1)],
+                    acc: 1
+                }
+            ]),
+            search(&generator("nop +0\njmp +0\nacc +1"), &options)
+        );
+        let options = PatchSearch {
+            budget: 2,
+            ..options
+        };
+        assert_eq!(
+            vec![
+                vec![Patch::Flip(1), Patch::Flip(2)],
+                vec![Patch::Flip(1), Patch::Delete(2)],
+                vec![Patch::Flip(2), Patch::Delete(1)],
+                vec![Patch::Delete(1), Patch::Delete(2)],
+            ],
+            search(&generator(TWO_FLIPS), &options)
+                .unwrap()
+                .into_iter()
+                .map(|x| x.patches)
+                .collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,505 @@
+use super::{Operation, Termination};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use z3::ast::{Ast, Bool, BV};
+use z3::{Config, Context, SatResult, Solver};
+
+/// acc is an isize in the vm, so everything is done on 64 bit vectors with the same wrapping
+const WIDTH: u32 = 64;
+
+/// a path still going after this many instructions is given up on, only a loop through a `jz` gets
+/// anywhere near it
+const STEP_LIMIT: usize = 1_000;
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Goal {
+    /// halts with exactly this acc
+    HaltsWith(isize),
+    /// halts with acc strictly greater than this
+    HaltsAbove(isize),
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Query {
+    /// addresses of `acc` instructions whose operand is left for the solver to pick
+    pub unknowns: Vec<usize>,
+    /// let the solver flip at most one nop/jmp as well
+    pub allow_patch: bool,
+    pub goal: Goal,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Answer {
+    /// chosen operand for every unknown
+    pub operands: BTreeMap<usize, isize>,
+    /// the flipped instruction, if one was needed
+    pub patch: Option<usize>,
+    pub acc: isize,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum SymbolicError {
+    NotAnAcc(usize),
+    /// no answer was found, but some paths ran past `STEP_LIMIT` so there might still be one
+    StepLimit,
+}
+
+impl fmt::Display for SymbolicError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SymbolicError::NotAnAcc(x) => write!(f, "instruction {} is not an acc", x),
+            SymbolicError::StepLimit => {
+                write!(
+                    f,
+                    "gave up on paths longer than {} instructions",
+                    STEP_LIMIT
+                )
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+struct State<'ctx> {
+    rip: isize,
+    acc: BV<'ctx>,
+    registers: HashMap<isize, BV<'ctx>>,
+    /// the same loop detection as `Machine::status`, with acc compared as an expression
+    last_visit: HashMap<usize, usize>,
+    last_branch: Option<usize>,
+    branches: HashSet<(isize, BV<'ctx>, BTreeMap<isize, BV<'ctx>>)>,
+    steps: usize,
+    /// everything which had to hold for control to come this way
+    condition: Vec<Bool<'ctx>>,
+    patch: Option<usize>,
+}
+
+/// a `jz` which can go two ways, flips never touch them
+fn is_branch(op: Operation) -> bool {
+    matches!(op, Operation::Jz(x) if x != 1)
+}
+
+impl<'ctx> State<'ctx> {
+    fn branch_state(&self) -> (isize, BV<'ctx>, BTreeMap<isize, BV<'ctx>>) {
+        let registers = self
+            .registers
+            .iter()
+            .map(|(k, v)| (*k, v.clone()))
+            .collect();
+        (self.rip, self.acc.clone(), registers)
+    }
+
+    fn repeats(&self, input: &[Operation]) -> bool {
+        let rip = self.rip as usize;
+        let again = match (self.last_visit.get(&rip), self.last_branch) {
+            (None, _) => false,
+            (Some(_), None) => true,
+            (Some(visit), Some(branch)) => *visit > branch,
+        };
+        again || (is_branch(input[rip]) && self.branches.contains(&self.branch_state()))
+    }
+}
+
+struct Path<'ctx> {
+    termination: Termination,
+    acc: BV<'ctx>,
+    condition: Vec<Bool<'ctx>>,
+    patch: Option<usize>,
+}
+
+/// every way through the program, forking on `jz` when acc isn't known and on flips when patching
+///
+/// without `jz` a path ends the first time it would repeat an instruction, so none of them are
+/// longer than the program, but each unknown `jz` can double the number of paths
+fn paths<'ctx>(
+    ctx: &'ctx Context,
+    input: &[Operation],
+    unknowns: &HashMap<usize, BV<'ctx>>,
+    allow_patch: bool,
+) -> (Vec<Path<'ctx>>, bool) {
+    let mut done = vec![];
+    let mut truncated = false;
+    let mut stack = vec![State {
+        rip: 0,
+        acc: BV::from_i64(ctx, 0, WIDTH),
+        registers: HashMap::new(),
+        last_visit: HashMap::new(),
+        last_branch: None,
+        branches: HashSet::new(),
+        steps: 0,
+        condition: vec![],
+        patch: None,
+    }];
+    while let Some(mut state) = stack.pop() {
+        if state.steps > STEP_LIMIT {
+            truncated = true;
+            continue;
+        }
+        let termination = if state.rip < 0 {
+            Some(Termination::JumpedBeforeStart)
+        } else if state.rip as usize > input.len() {
+            Some(Termination::JumpedPastEnd)
+        } else if state.rip as usize == input.len() {
+            Some(Termination::Halted)
+        } else if state.repeats(input) {
+            Some(Termination::Looped)
+        } else {
+            None
+        };
+        if let Some(termination) = termination {
+            done.push(Path {
+                termination,
+                acc: state.acc,
+                condition: state.condition,
+                patch: state.patch,
+            });
+            continue;
+        }
+
+        let rip = state.rip as usize;
+        // a single patch is there from the start, so it can only be chosen the first time round
+        let first_visit = state.last_visit.insert(rip, state.steps).is_none();
+        if is_branch(input[rip]) {
+            state.branches.insert(state.branch_state());
+            state.last_branch = Some(state.steps);
+        }
+        state.steps += 1;
+        let mut op = input[rip];
+        if allow_patch && first_visit && state.patch.is_none() && op.flip() != op {
+            // pushed first so it is popped after the unpatched state
+            let mut flipped = state.clone();
+            flipped.patch = Some(rip);
+            flipped.rip = advance(&mut flipped, op.flip(), unknowns);
+            stack.push(flipped);
+        }
+        if state.patch == Some(rip) {
+            op = op.flip();
+        }
+        if let Operation::Jz(x) = op {
+            let zero = state.acc._eq(&BV::from_i64(ctx, 0, WIDTH)).simplify();
+            match zero.as_bool() {
+                Some(true) => state.rip = state.rip.saturating_add(x),
+                Some(false) => state.rip += 1,
+                None => {
+                    let mut taken = state.clone();
+                    taken.rip = taken.rip.saturating_add(x);
+                    taken.condition.push(zero.clone());
+                    state.rip += 1;
+                    state.condition.push(zero.not());
+                    stack.push(taken);
+                }
+            }
+        } else {
+            state.rip = advance(&mut state, op, unknowns);
+        }
+        stack.push(state);
+    }
+    (done, truncated)
+}
+
+/// execute anything but `jz` and return the next rip
+fn advance<'ctx>(
+    state: &mut State<'ctx>,
+    op: Operation,
+    unknowns: &HashMap<usize, BV<'ctx>>,
+) -> isize {
+    let ctx = state.acc.get_ctx();
+    let rip = state.rip as usize;
+    match op {
+        Operation::Jmp(x) => return state.rip.saturating_add(x),
+        Operation::Acc(x) => {
+            let operand = match unknowns.get(&rip) {
+                Some(unknown) => unknown.clone(),
+                None => BV::from_i64(ctx, x as i64, WIDTH),
+            };
+            state.acc = state.acc.bvadd(&operand).simplify();
+        }
+        Operation::Mul(x) => {
+            state.acc = state
+                .acc
+                .bvmul(&BV::from_i64(ctx, x as i64, WIDTH))
+                .simplify()
+        }
+        Operation::Sto(register) => {
+            state.registers.insert(register, state.acc.clone());
+        }
+        Operation::Lod(register) => {
+            state.acc = match state.registers.get(&register) {
+                Some(x) => x.clone(),
+                None => BV::from_i64(ctx, 0, WIDTH),
+            }
+        }
+        Operation::Nop(_) | Operation::Out(_) | Operation::Jz(_) => {}
+    }
+    state.rip + 1
+}
+
+/// look for operands, and maybe one flip, which make the program reach `goal`
+///
+/// `Ok(None)` means the solver proved there aren't any, if it had to give up on some paths without
+/// finding an answer on the others that is `Err(SymbolicError::StepLimit)` instead, with
+/// `allow_patch` an unpatched solution is preferred over a patched one
+pub fn solve(input: &[Operation], query: &Query) -> Result<Option<Answer>, SymbolicError> {
+    if let Some(x) = query
+        .unknowns
+        .iter()
+        .find(|x| !matches!(input.get(**x), Some(Operation::Acc(_))))
+    {
+        return Err(SymbolicError::NotAnAcc(*x));
+    }
+    let ctx = Context::new(&Config::new());
+    let unknowns = query
+        .unknowns
+        .iter()
+        .map(|x| (*x, BV::new_const(&ctx, format!("acc{}", x), WIDTH)))
+        .collect::<HashMap<_, _>>();
+    let solver = Solver::new(&ctx);
+    let (paths, truncated) = paths(&ctx, input, &unknowns, query.allow_patch);
+    let mut halted = paths
+        .into_iter()
+        .filter(|x| x.termination == Termination::Halted)
+        .collect::<Vec<_>>();
+    // a forked jz can leave the unpatched path below patched ones on the stack
+    halted.sort_by_key(|x| x.patch.is_some());
+    for path in halted {
+        let goal = match query.goal {
+            Goal::HaltsWith(x) => path.acc._eq(&BV::from_i64(&ctx, x as i64, WIDTH)),
+            Goal::HaltsAbove(x) => path.acc.bvsgt(&BV::from_i64(&ctx, x as i64, WIDTH)),
+        };
+        solver.push();
+        for condition in &path.condition {
+            solver.assert(condition);
+        }
+        solver.assert(&goal);
+        if solver.check() == SatResult::Sat {
+            let model = solver.get_model().unwrap();
+            // z3 only hands out bit vectors as unsigned numbers
+            let value = |x: &BV| model.eval(x).unwrap().as_u64().unwrap() as isize;
+            return Ok(Some(Answer {
+                operands: unknowns.iter().map(|(k, v)| (*k, value(v))).collect(),
+                patch: path.patch,
+                acc: value(&path.acc),
+            }));
+        }
+        solver.pop(1);
+    }
+    if truncated {
+        Err(SymbolicError::StepLimit)
+    } else {
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{execute_from, generator};
+    use super::*;
+
+    const PROVIDED: &str = "nop +0
+acc +1
+jmp +4
+acc +3
+jmp -3
+acc -99
+acc +1
+jmp -4
+acc +6";
+
+    /// put the chosen operands and patch into the program and run it for real
+    fn check(input: &[Operation], answer: &Answer) -> (Termination, isize) {
+        let mut program = input.to_vec();
+        for (index, operand) in &answer.operands {
+            program[*index] = Operation::Acc(*operand);
+        }
+        if let Some(x) = answer.patch {
+            program[x] = program[x].flip();
+        }
+        let report = execute_from(&program, 0);
+        (report.termination, report.acc)
+    }
+
+    #[test]
+    fn it_finds_operands() {
+        let provided = generator("acc +1\nacc +2\nacc +3");
+        let query = Query {
+            unknowns: vec![1],
+            allow_patch: false,
+            goal: Goal::HaltsWith(42),
+        };
+        let answer = solve(&provided, &query).unwrap().unwrap();
+        assert_eq!(Some(&38), answer.operands.get(&1));
+        assert_eq!(42, answer.acc);
+        assert_eq!((Termination::Halted, 42), check(&provided, &answer));
+    }
+
+    #[test]
+    fn it_finds_negative_values() {
+        let provided = generator("acc +1\nacc +2");
+        let query = Query {
+            unknowns: vec![1],
+            allow_patch: false,
+            goal: Goal::HaltsWith(-5),
+        };
+        let answer = solve(&provided, &query).unwrap().unwrap();
+        assert_eq!(Some(&-6), answer.operands.get(&1));
+        assert_eq!(-5, answer.acc);
+        assert_eq!((Termination::Halted, -5), check(&provided, &answer));
+    }
+
+    #[test]
+    fn it_prefers_no_patch() {
+        // flipping the jmp halts whichever way the jz goes, but the unpatched program halts too
+        // when the jz is taken
+        let provided = generator("acc +0\njz +2\njmp +0\nacc +1");
+        let query = Query {
+            unknowns: vec![0],
+            allow_patch: true,
+            goal: Goal::HaltsAbove(0),
+        };
+        let answer = solve(&provided, &query).unwrap().unwrap();
+        assert_eq!(None, answer.patch);
+        assert_eq!((Termination::Halted, 1), check(&provided, &answer));
+    }
+
+    #[test]
+    fn it_only_patches_before_the_first_visit() {
+        // flipping the jmp -2 once the countdown has gone round would give acc 1, but a patched
+        // program runs the flipped instruction every time, which halts with acc 2
+        let provided = generator("acc +3\nacc -1\njz +3\njmp -2\njmp +2\njmp +0");
+        let query = Query {
+            unknowns: vec![],
+            allow_patch: true,
+            goal: Goal::HaltsWith(1),
+        };
+        assert_eq!(Ok(None), solve(&provided, &query));
+        let query = Query {
+            goal: Goal::HaltsWith(2),
+            ..query
+        };
+        let answer = solve(&provided, &query).unwrap().unwrap();
+        assert_eq!(Some(3), answer.patch);
+        assert_eq!((Termination::Halted, 2), check(&provided, &answer));
+    }
+
+    #[test]
+    fn it_gives_up_on_long_paths() {
+        // acc only comes back to zero after wrapping round
+        let provided = generator("acc +1\njz +2\njmp -2\nnop +0");
+        let query = Query {
+            unknowns: vec![],
+            allow_patch: false,
+            goal: Goal::HaltsWith(0),
+        };
+        assert_eq!(Err(SymbolicError::StepLimit), solve(&provided, &query));
+    }
+
+    #[test]
+    fn it_follows_jz_on_unknowns() {
+        // halting needs acc to be zero at the jz, which forces the first operand
+        let provided = generator("acc +5\njz +2\njmp +0\nmul +3\nacc +7");
+        let query = Query {
+            unknowns: vec![0, 4],
+            allow_patch: false,
+            goal: Goal::HaltsWith(10),
+        };
+        let answer = solve(&provided, &query).unwrap().unwrap();
+        assert_eq!(Some(&0), answer.operands.get(&0));
+        assert_eq!(Some(&10), answer.operands.get(&4));
+        assert_eq!((Termination::Halted, 10), check(&provided, &answer));
+    }
+
+    #[test]
+    fn it_goes_round_loops_through_jz() {
+        // counts acc down to zero, the unknown sets how many times round the loop
+        let provided = generator("acc +3\nacc -1\njz +2\njmp -2\nout +0");
+        let query = Query {
+            unknowns: vec![0],
+            allow_patch: false,
+            goal: Goal::HaltsWith(0),
+        };
+        let answer = solve(&provided, &query).unwrap().unwrap();
+        assert_eq!((Termination::Halted, 0), check(&provided, &answer));
+        let query = Query {
+            unknowns: vec![],
+            ..query
+        };
+        assert_eq!(0, solve(&provided, &query).unwrap().unwrap().acc);
+    }
+
+    #[test]
+    fn it_proves_there_is_no_answer() {
+        let query = Query {
+            unknowns: vec![],
+            allow_patch: false,
+            goal: Goal::HaltsAbove(0),
+        };
+        assert_eq!(Ok(None), solve(&generator(PROVIDED), &query));
+        // acc*2 is always even
+        let query = Query {
+            unknowns: vec![0],
+            allow_patch: false,
+            goal: Goal::HaltsWith(7),
+        };
+        assert_eq!(Ok(None), solve(&generator("acc +1\nmul +2"), &query));
+    }
+
+    #[test]
+    fn it_finds_a_patch() {
+        let provided = generator(PROVIDED);
+        let query = Query {
+            unknowns: vec![],
+            allow_patch: true,
+            goal: Goal::HaltsAbove(0),
+        };
+        assert_eq!(
+            Some(Answer {
+                operands: BTreeMap::new(),
+                patch: Some(7),
+                acc: 8
+            }),
+            solve(&provided, &query).unwrap()
+        );
+        let query = Query {
+            goal: Goal::HaltsAbove(8),
+            ..query
+        };
+        assert_eq!(Ok(None), solve(&provided, &query));
+    }
+
+    #[test]
+    fn it_combines_patches_and_unknowns() {
+        let provided = generator(PROVIDED);
+        let query = Query {
+            unknowns: vec![6],
+            allow_patch: true,
+            goal: Goal::HaltsWith(100),
+        };
+        let answer = solve(&provided, &query).unwrap().unwrap();
+        assert_eq!(Some(7), answer.patch);
+        assert_eq!((Termination::Halted, 100), check(&provided, &answer));
+    }
+
+    #[test]
+    fn it_solves_part2_of_real_input() {
+        let query = Query {
+            unknowns: vec![],
+            allow_patch: true,
+            goal: Goal::HaltsAbove(isize::MIN),
+        };
+        let provided = generator(include_str!("../../input/2020/day8.txt"));
+        assert_eq!(1245, solve(&provided, &query).unwrap().unwrap().acc);
+    }
+
+    #[test]
+    fn it_rejects_unknowns_which_are_not_acc() {
+        let query = Query {
+            unknowns: vec![0],
+            allow_patch: false,
+            goal: Goal::HaltsWith(0),
+        };
+        assert_eq!(
+            Err(SymbolicError::NotAnAcc(0)),
+            solve(&generator(PROVIDED), &query)
+        );
+    }
+}
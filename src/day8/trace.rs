@@ -0,0 +1,256 @@
+use super::{Machine, Operation, StepOutcome};
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct TraceEntry {
+    pub step: usize,
+    pub rip: usize,
+    pub op: Operation,
+    pub acc_before: isize,
+    pub acc_after: isize,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Default, Serialize, Deserialize)]
+pub struct Trace {
+    pub entries: Vec<TraceEntry>,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum TraceError {
+    Malformed(String),
+    /// the program doesn't do what the trace says it did at this step
+    Diverged {
+        step: usize,
+    },
+    /// the trace doesn't reach this step
+    OutOfRange {
+        step: usize,
+    },
+}
+
+impl fmt::Display for TraceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TraceError::Malformed(x) => write!(f, "can't parse trace: {}", x),
+            TraceError::Diverged { step } => {
+                write!(f, "program diverges from trace at step {}", step)
+            }
+            TraceError::OutOfRange { step } => write!(f, "trace has no step {}", step),
+        }
+    }
+}
+
+impl Serialize for Operation {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Operation {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        match super::parse_program(&text)
+            .map_err(D::Error::custom)?
+            .as_slice()
+        {
+            [op] => Ok(*op),
+            _ => Err(D::Error::custom(format!(
+                "expected one instruction, got {:?}",
+                text
+            ))),
+        }
+    }
+}
+
+impl Trace {
+    pub fn new() -> Self {
+        Trace::default()
+    }
+
+    /// step the machine, recording the instruction if one was executed
+    pub fn step(&mut self, machine: &mut Machine) -> StepOutcome {
+        if machine.status() != StepOutcome::Continued {
+            return machine.status();
+        }
+        let rip = machine.rip as usize;
+        let acc_before = machine.acc;
+        let outcome = machine.step();
+        self.entries.push(TraceEntry {
+            step: self.entries.len(),
+            rip,
+            op: machine.program[rip],
+            acc_before,
+            acc_after: machine.acc,
+        });
+        outcome
+    }
+
+    pub fn run(&mut self, machine: &mut Machine) -> StepOutcome {
+        while self.step(machine) == StepOutcome::Continued {}
+        machine.status()
+    }
+
+    /// one `step rip op param acc_before acc_after` line per executed instruction
+    pub fn to_text(&self) -> String {
+        self.entries
+            .iter()
+            .map(|x| {
+                format!(
+                    "{} {} {} {} {}\n",
+                    x.step, x.rip, x.op, x.acc_before, x.acc_after
+                )
+            })
+            .collect()
+    }
+
+    pub fn from_text(input: &str) -> Result<Self, TraceError> {
+        fn number<T: std::str::FromStr>(x: &str) -> Result<T, TraceError> {
+            x.parse().map_err(|_| TraceError::Malformed(x.to_string()))
+        }
+        let entries = input
+            .lines()
+            .filter(|x| !x.trim().is_empty())
+            .map(
+                |line| match line.split_whitespace().collect::<Vec<_>>().as_slice() {
+                    [step, rip, mnemonic, param, acc_before, acc_after] => Ok(TraceEntry {
+                        step: number(step)?,
+                        rip: number(rip)?,
+                        op: Operation::from_mnemonic(mnemonic, number(param)?)
+                            .ok_or_else(|| TraceError::Malformed(line.to_string()))?,
+                        acc_before: number(acc_before)?,
+                        acc_after: number(acc_after)?,
+                    }),
+                    _ => Err(TraceError::Malformed(line.to_string())),
+                },
+            )
+            .collect::<Result<_, _>>()?;
+        Ok(Trace { entries })
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    pub fn from_json(input: &str) -> Result<Self, TraceError> {
+        serde_json::from_str(input).map_err(|x| TraceError::Malformed(x.to_string()))
+    }
+
+    /// rebuild the machine as it was just before `step` by re-running `program` against the trace
+    pub fn replay(&self, program: Vec<Operation>, step: usize) -> Result<Machine, TraceError> {
+        if step > self.entries.len() {
+            return Err(TraceError::OutOfRange { step });
+        }
+        let base = self.entries.first().map(|x| x.rip).unwrap_or(0);
+        let mut machine = Machine::starting_at(program, base);
+        // the trace is already finite, however long it ran
+        machine.step_limit = usize::MAX;
+        for entry in &self.entries[..step] {
+            let matches = machine.status() == StepOutcome::Continued
+                && machine.rip as usize == entry.rip
+                && machine.acc == entry.acc_before
+                && machine.program[entry.rip] == entry.op;
+            if !matches {
+                return Err(TraceError::Diverged { step: entry.step });
+            }
+            machine.step();
+            if machine.acc != entry.acc_after {
+                return Err(TraceError::Diverged { step: entry.step });
+            }
+        }
+        Ok(machine)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::generator;
+    use super::*;
+
+    const PROVIDED: &str = "nop +0
+acc +1
+jmp +4
+acc +3
+jmp -3
+acc -99
+acc +1
+jmp -4
+acc +6";
+
+    fn recorded() -> Trace {
+        let mut trace = Trace::new();
+        assert_eq!(
+            StepOutcome::Looped,
+            trace.run(&mut Machine::new(generator(PROVIDED)))
+        );
+        trace
+    }
+
+    #[test]
+    fn it_records() {
+        let trace = recorded();
+        assert_eq!(7, trace.entries.len());
+        assert_eq!(
+            TraceEntry {
+                step: 4,
+                rip: 7,
+                op: Operation::Jmp(-4),
+                acc_before: 2,
+                acc_after: 2
+            },
+            trace.entries[4]
+        );
+        assert_eq!(5, trace.entries.last().unwrap().acc_after);
+    }
+
+    #[test]
+    fn it_round_trips_text() {
+        let trace = recorded();
+        assert!(trace
+            .to_text()
+            .starts_with("0 0 nop +0 0 0\n1 1 acc +1 0 1\n"));
+        assert_eq!(Ok(trace.clone()), Trace::from_text(&trace.to_text()));
+        assert!(Trace::from_text("0 0 hcf +0 0 0").is_err());
+    }
+
+    #[test]
+    fn it_round_trips_json() {
+        let trace = recorded();
+        assert!(trace.to_json().starts_with(
+            r#"{"entries":[{"step":0,"rip":0,"op":"nop +0","acc_before":0,"acc_after":0}"#
+        ));
+        assert_eq!(Ok(trace.clone()), Trace::from_json(&trace.to_json()));
+        assert!(Trace::from_json(
+            r#"{"entries":[{"step":0,"rip":0,"op":"nop","acc_before":0,"acc_after":0}]}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn it_replays() {
+        let trace = recorded();
+        let machine = trace.replay(generator(PROVIDED), 4).unwrap();
+        assert_eq!((7, 2), (machine.rip, machine.acc));
+        let machine = trace.replay(generator(PROVIDED), 7).unwrap();
+        assert_eq!(
+            (1, 5, StepOutcome::Looped),
+            (machine.rip, machine.acc, machine.status())
+        );
+        assert_eq!(
+            Err(TraceError::OutOfRange { step: 8 }),
+            trace.replay(generator(PROVIDED), 8)
+        );
+    }
+
+    #[test]
+    fn it_detects_divergence() {
+        let trace = recorded();
+        let patched = generator(&PROVIDED.replace("acc +3", "acc +4"));
+        assert!(trace.replay(patched.clone(), 5).is_ok());
+        assert_eq!(
+            Err(TraceError::Diverged { step: 5 }),
+            trace.replay(patched, 6)
+        );
+    }
+}
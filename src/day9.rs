@@ -1,4 +1,7 @@
-use std::collections::HashSet;
+use crate::overflow::{self, Overflow};
+use num_traits::PrimInt;
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
 
 /// https://adventofcode.com/2020/day/9
 
@@ -10,44 +13,159 @@ pub fn generator(input: &str) -> Vec<usize> {
         .collect()
 }
 
-pub fn two_sum(input: &[usize], sum: usize) -> Option<(usize, usize)> {
-    input
-        .iter()
-        .zip(std::iter::repeat(
-            &input.iter().cloned().collect::<HashSet<usize>>(),
-        ))
-        .filter(|(x, _)| **x < sum)
-        .find(|(x, nums)| {
-            nums.get(&(sum - **x)).is_some() && nums.get(&(sum - **x)).map(|y| **x != *y).unwrap()
-        })
-        .map(|(x, _)| (*x, (sum - *x)))
+/// the first of `candidates` which pairs up with another entry of `counts` to make `sum`
+///
+/// the pair has to be two different entries, so a value only pairs with itself if it's there twice
+fn find_pair<T: PrimInt + Hash>(
+    counts: &HashMap<T, usize>,
+    mut candidates: impl Iterator<Item = T>,
+    sum: T,
+) -> Option<(T, T)> {
+    candidates.find_map(|x| {
+        let y = sum.checked_sub(&x)?;
+        match counts.get(&y) {
+            Some(count) if y != x || *count > 1 => Some((x, y)),
+            _ => None,
+        }
+    })
+}
+
+fn counts<T: PrimInt + Hash>(input: impl IntoIterator<Item = T>) -> HashMap<T, usize> {
+    let mut counts = HashMap::new();
+    for x in input {
+        *counts.entry(x).or_insert(0) += 1;
+    }
+    counts
+}
+
+/// two entries of `input` adding up to `sum`, the first one being as early in the input as possible
+pub fn two_sum<T: PrimInt + Hash>(input: &[T], sum: T) -> Option<(T, T)> {
+    find_pair(&counts(input.iter().cloned()), input.iter().cloned(), sum)
+}
+
+/// yields `(index, number)` for every number which isn't the sum of two entries among the
+/// `preamble` numbers before it, reading the input one number at a time
+pub struct XmasValidator<T, I: Iterator<Item = T>> {
+    input: I,
+    preamble: usize,
+    index: usize,
+    window: VecDeque<T>,
+    /// how many times each value is in the window
+    counts: HashMap<T, usize>,
+}
+
+impl<T: PrimInt + Hash, I: Iterator<Item = T>> XmasValidator<T, I> {
+    pub fn new(input: I, preamble: usize) -> Self {
+        XmasValidator {
+            input,
+            preamble,
+            index: 0,
+            window: VecDeque::with_capacity(preamble + 1),
+            counts: HashMap::new(),
+        }
+    }
+
+    fn is_valid(&self, number: T) -> bool {
+        find_pair(&self.counts, self.counts.keys().cloned(), number).is_some()
+    }
+
+    fn push(&mut self, number: T) {
+        self.window.push_back(number);
+        *self.counts.entry(number).or_insert(0) += 1;
+        if self.window.len() > self.preamble {
+            let old = self.window.pop_front().unwrap();
+            let count = self.counts.get_mut(&old).unwrap();
+            *count -= 1;
+            if *count == 0 {
+                self.counts.remove(&old);
+            }
+        }
+    }
+}
+
+impl<T: PrimInt + Hash, I: Iterator<Item = T>> Iterator for XmasValidator<T, I> {
+    type Item = (usize, T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(number) = self.input.next() {
+            let index = self.index;
+            self.index += 1;
+            let invalid = index >= self.preamble && !self.is_valid(number);
+            self.push(number);
+            if invalid {
+                return Some((index, number));
+            }
+        }
+        None
+    }
 }
 
 #[aoc(day9, part1)]
 pub fn solve_part1(input: &[usize]) -> usize {
-    input
-        .windows(26)
-        .map(|x| (x[..25].to_vec(), x[25]))
-        .find(|(a, b)| two_sum(&a.clone(), *b).is_none())
-        .map(|(_, b)| b)
+    XmasValidator::new(input.iter().cloned(), 25)
+        .next()
+        .map(|(_, x)| x)
         .unwrap()
 }
 
-pub fn search_window(input: &[usize], length: usize, sum: usize) -> Option<Vec<usize>> {
-    input
-        .windows(length)
-        .find(|x| x.iter().sum::<usize>() == sum)
-        .map(|x| x.to_vec())
+/// the first window of `length` numbers adding up to `sum`, failing if a window total doesn't fit in `T`
+pub fn search_window<T: PrimInt>(
+    input: &[T],
+    length: usize,
+    sum: T,
+) -> Result<Option<Vec<T>>, Overflow> {
+    for window in input.windows(length) {
+        if overflow::sum(window)? == sum {
+            return Ok(Some(window.to_vec()));
+        }
+    }
+    Ok(None)
+}
+
+/// every `(start, end)` with `input[start..end]` summing to `target`, sorted by start then end
+///
+/// two ranges share a sum exactly when the prefix sums at their ends differ by it, so one pass
+/// with a map from prefix sum to the positions it occurred at finds them all, as long as every
+/// prefix sum fits in `T`
+pub fn find_contiguous_sum<T: PrimInt + Hash>(
+    input: &[T],
+    target: T,
+) -> Result<Vec<(usize, usize)>, Overflow> {
+    let mut starts: HashMap<T, Vec<usize>> = HashMap::new();
+    let mut ranges = vec![];
+    let mut prefix = T::zero();
+    starts.insert(prefix, vec![0]);
+    for (index, x) in input.iter().enumerate() {
+        prefix = overflow::add(prefix, *x)?;
+        // a start needing a prefix sum outside of `T` can't exist
+        if let Some(found) = prefix.checked_sub(&target).and_then(|x| starts.get(&x)) {
+            ranges.extend(found.iter().map(|start| (*start, index + 1)));
+        }
+        starts.entry(prefix).or_default().push(index + 1);
+    }
+    ranges.sort_unstable();
+    Ok(ranges)
+}
+
+/// the smallest plus the largest number in the first run of at least two adding up to `sum`
+pub fn weakness<T: PrimInt + Hash>(input: &[T], sum: T) -> Result<Option<T>, Overflow> {
+    let range = find_contiguous_sum(input, sum)?
+        .into_iter()
+        .find(|(start, end)| end - start >= 2);
+    match range {
+        Some((start, end)) => {
+            let solution = &input[start..end];
+            let smallest = *solution.iter().min().unwrap();
+            let largest = *solution.iter().max().unwrap();
+            overflow::add(smallest, largest).map(Some)
+        }
+        None => Ok(None),
+    }
 }
 
 #[aoc(day9, part2)]
 pub fn solve_part2(input: &[usize]) -> usize {
-    let sum = solve_part1(input);
-    let solution = (2..input.len())
-        .filter_map(|x| search_window(input, x, sum))
-        .next()
-        .unwrap();
-    solution.iter().min().unwrap() + solution.iter().max().unwrap()
+    weakness(input, solve_part1(input)).unwrap().unwrap()
 }
 
 #[cfg(test)]
@@ -59,6 +177,28 @@ mod tests {
         assert_eq!(Some((15, 25)), two_sum(&[35, 20, 15, 25, 47], 40))
     }
 
+    #[test]
+    fn it_pairs_repeated_values() {
+        assert_eq!(Some((5, 5)), two_sum(&[5, 5], 10));
+        assert_eq!(Some((5, 5)), two_sum(&[1, 5, 2, 5], 10));
+        assert_eq!(Some((4, 6)), two_sum(&[4, 5, 6], 10));
+        assert_eq!(None, two_sum(&[5, 1, 2], 10));
+        assert_eq!(None, two_sum(&[5], 10));
+        assert_eq!(Some((0, 0)), two_sum(&[0, 0], 0));
+        assert_eq!(None, two_sum(&[0, 7], 0));
+    }
+
+    #[test]
+    fn it_validates_repeated_values_in_the_window() {
+        // 10 is fine while both 5s are in the window and invalid once one drops out, then 20 is
+        // the two 10s
+        let provided = vec![5, 5, 10, 10, 20, 15];
+        assert_eq!(
+            vec![(3, 10), (5, 15)],
+            XmasValidator::new(provided.into_iter(), 2).collect::<Vec<_>>()
+        );
+    }
+
     #[test]
     fn it_generates() {
         let provided = "35
@@ -84,6 +224,34 @@ mod tests {
         assert_eq!(20, generator(provided).len());
     }
 
+    #[test]
+    fn it_validates_with_a_short_preamble() {
+        let provided = vec![
+            35, 20, 15, 25, 47, 40, 62, 55, 65, 95, 102, 117, 150, 182, 127, 219, 299, 277, 309,
+            576,
+        ];
+        assert_eq!(
+            vec![(14, 127)],
+            XmasValidator::new(provided.into_iter(), 5).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn it_yields_every_invalid_number() {
+        // 6 is 3 + 3 from two separate entries
+        let provided = vec![1, 2, 3, 3, 100, 6, 7];
+        assert_eq!(
+            vec![(4, 100), (6, 7)],
+            XmasValidator::new(provided.into_iter(), 3).collect::<Vec<_>>()
+        );
+        // 2 can't be 1 + 1 once the first 1 is gone
+        let provided = vec![1, 1, 2, 2];
+        assert_eq!(
+            vec![(3, 2)],
+            XmasValidator::new(provided.into_iter(), 2).collect::<Vec<_>>()
+        );
+    }
+
     #[test]
     fn it_solves_part1() {
         let provided = include_str!("../input/2020/day9.txt");
@@ -96,4 +264,83 @@ mod tests {
         let provided = include_str!("../input/2020/day9.txt");
         assert_eq!(2986195, solve_part2(&generator(provided)));
     }
+
+    #[test]
+    fn it_finds_contiguous_sums_in_real_input() {
+        let provided = generator(include_str!("../input/2020/day9.txt"));
+        let ranges = find_contiguous_sum(&provided, 21806024).unwrap();
+        let single = provided.iter().position(|x| *x == 21806024).unwrap();
+        assert!(ranges.contains(&(single, single + 1)));
+        let long = ranges
+            .iter()
+            .filter(|(start, end)| end - start >= 2)
+            .collect::<Vec<_>>();
+        assert_eq!(1, long.len());
+        assert_eq!(
+            Some(provided[long[0].0..long[0].1].to_vec()),
+            search_window(&provided, long[0].1 - long[0].0, 21806024).unwrap()
+        );
+    }
+
+    #[test]
+    fn it_finds_contiguous_sums_with_zeros() {
+        assert_eq!(
+            Ok(vec![(0, 1), (0, 2), (0, 3), (1, 2), (1, 3), (2, 3)]),
+            find_contiguous_sum(&[0, 0, 0], 0)
+        );
+        assert_eq!(
+            Ok(vec![(0, 2), (0, 3), (1, 4)]),
+            find_contiguous_sum(&[2, 3, 0, 2], 5)
+        );
+        assert_eq!(Ok(vec![]), find_contiguous_sum(&[4, 4, 4], 5));
+        assert_eq!(Ok(vec![]), find_contiguous_sum::<usize>(&[], 0));
+    }
+
+    #[test]
+    fn it_works_for_other_integers() {
+        let provided = vec![5i64, -3, 2, -1, 1, -20, 0];
+        assert_eq!(
+            vec![(5, -20), (6, 0)],
+            XmasValidator::new(provided.iter().cloned(), 2).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            Ok(vec![(3, 6), (3, 7), (5, 6), (5, 7)]),
+            find_contiguous_sum(&provided, -20)
+        );
+        assert_eq!(Ok(Some(-19)), weakness(&provided, -20));
+        let big = vec![u128::MAX / 2, 1, u128::MAX / 2 + 1];
+        assert_eq!(
+            Some((1, u128::MAX / 2 + 1)),
+            two_sum(&big, u128::MAX / 2 + 2)
+        );
+        assert_eq!(
+            Ok(Some(vec![u128::MAX / 2, 1])),
+            search_window(&big, 2, u128::MAX / 2 + 1)
+        );
+    }
+
+    #[test]
+    fn it_reports_overflow() {
+        let provided = vec![u64::MAX - 1, 1, 1];
+        assert_eq!(Err(Overflow::Add), find_contiguous_sum(&provided, 2));
+        assert_eq!(Err(Overflow::Add), search_window(&provided, 3, 2));
+        assert_eq!(
+            Ok(Some(vec![u64::MAX - 1, 1])),
+            search_window(&provided, 2, u64::MAX)
+        );
+        // numbers bigger than the one being checked are just skipped
+        assert_eq!(
+            vec![(2, 3)],
+            XmasValidator::new(vec![u64::MAX, 1, 3].into_iter(), 2).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn it_finds_contiguous_sums_in_long_runs() {
+        // the old window search would add up about 10^14 numbers here
+        let provided = vec![1; 200_000];
+        let ranges = find_contiguous_sum(&provided, 100_000).unwrap();
+        assert_eq!(100_001, ranges.len());
+        assert!(ranges.iter().all(|(start, end)| end - start == 100_000));
+    }
 }
@@ -1,20 +1,20 @@
-mod day1;
+pub mod day1;
 mod day2;
 mod day3;
 mod day4;
 mod day5;
 mod day6;
-mod day7;
-mod day8;
-mod day9;
-mod day10;
+pub mod day7;
+pub mod day8;
+pub mod day9;
+pub mod day10;
 mod day11;
 mod day12;
 mod day13;
+pub mod overflow;
 
 #[macro_use]
 extern crate aoc_runner_derive;
-extern crate cached;
 use aoc_runner_derive::aoc_lib;
 
 
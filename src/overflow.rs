@@ -0,0 +1,55 @@
+use num_traits::PrimInt;
+use std::fmt;
+
+/// an intermediate result didn't fit in the integer type the puzzle is being solved with
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Overflow {
+    Add,
+    Sub,
+    Mul,
+}
+
+impl fmt::Display for Overflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Overflow::Add => write!(f, "addition overflowed"),
+            Overflow::Sub => write!(f, "subtraction overflowed"),
+            Overflow::Mul => write!(f, "multiplication overflowed"),
+        }
+    }
+}
+
+pub fn add<T: PrimInt>(a: T, b: T) -> Result<T, Overflow> {
+    a.checked_add(&b).ok_or(Overflow::Add)
+}
+
+pub fn sub<T: PrimInt>(a: T, b: T) -> Result<T, Overflow> {
+    a.checked_sub(&b).ok_or(Overflow::Sub)
+}
+
+pub fn mul<T: PrimInt>(a: T, b: T) -> Result<T, Overflow> {
+    a.checked_mul(&b).ok_or(Overflow::Mul)
+}
+
+pub fn sum<'a, T: PrimInt + 'a>(input: impl IntoIterator<Item = &'a T>) -> Result<T, Overflow> {
+    input.into_iter().try_fold(T::zero(), |acc, x| add(acc, *x))
+}
+
+pub fn product<'a, T: PrimInt + 'a>(input: impl IntoIterator<Item = &'a T>) -> Result<T, Overflow> {
+    input.into_iter().try_fold(T::one(), |acc, x| mul(acc, *x))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_reports_overflow() {
+        assert_eq!(Ok(255u8), sum(&[200u8, 55]));
+        assert_eq!(Err(Overflow::Add), sum(&[200u8, 56]));
+        assert_eq!(Err(Overflow::Sub), sub(0u64, 1));
+        assert_eq!(Ok(-128i8), product(&[-64i8, 2]));
+        assert_eq!(Err(Overflow::Mul), product(&[64i8, 2]));
+        assert_eq!(Ok(u128::MAX), sum(&[u128::MAX, 0]));
+    }
+}